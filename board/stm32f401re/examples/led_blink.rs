@@ -2,35 +2,20 @@
 
 extern crate stm32f401re;
 
-use stm32f401re::peripheral::{rcc, gpio};
+use stm32f401re::delay::Delay;
+use stm32f401re::peripheral::{gpio, rcc, Peripherals};
 
 fn main() {
-    let rcc = rcc::RCC_BASE as *const rcc::RegisterMap;
-    let gpioa = gpio::GPIOA_BASE as *const gpio::RegisterMap;
-    const PIN_A5: u32 = 1 << 5;
+    let p = Peripherals::take().unwrap();
+    let clocks = rcc::Config::hsi().freeze(&p.RCC, &p.FLASH, &p.PWR).unwrap();
+    let mut delay = Delay::new(&clocks);
+    let gpioa = p.GPIOA.split(&p.RCC);
 
-    unsafe {
-        (*rcc).ahb1enr.modify(
-            |v| v | rcc::ahb1enr::Gpioaen::Enable as u32,
-        );
-        (*gpioa).moder.modify(|v| {
-            (v & !((0b11 as u32) << (5 * 2))) | ((gpio::moder::Modery::Output as u32) << (5 * 2))
-        });
-        (*gpioa).otyper.modify(|v| {
-            (v & !((0b1 as u32) << 5)) | ((gpio::otyper::Oty::PushPull as u32) << 5)
-        });
-        (*gpioa).ospeedr.modify(|v| {
-            (v & !((0b11 as u32) << (5 * 2))) | ((gpio::ospeedr::Ospeedr::High as u32) << (5 * 2))
-        });
-        (*gpioa).pupdr.modify(|v| {
-            (v & !((0b11 as u32) << (5 * 2))) | (gpio::pupdr::Pupdr::NoPuPd as u32) << (5 * 2)
-        });
-    }
+    let mut led = gpioa.p5.into_push_pull_output();
+    led.set_speed(gpio::ospeedr::Ospeedr::High);
 
     loop {
-        unsafe {
-            (*gpioa).odr.modify(|v| v ^ PIN_A5);
-        }
-        stm32f401re::delay(1000);
+        led.toggle();
+        delay.delay_ms(500);
     }
 }
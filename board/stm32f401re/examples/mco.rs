@@ -0,0 +1,26 @@
+#![no_std]
+
+extern crate stm32f401re;
+
+use stm32f401re::peripheral::{rcc, Peripherals};
+
+fn main() {
+    let p = Peripherals::take().unwrap();
+    rcc::Config::hsi()
+        .sysclk(84_000_000)
+        .pclk1(42_000_000)
+        .freeze(&p.RCC, &p.FLASH, &p.PWR)
+        .unwrap();
+
+    let gpioa = p.GPIOA.split(&p.RCC);
+
+    // PLL output (84MHz) / 4 = 21MHz on PA8 while the guard lives
+    let _mco = rcc::Mco::mco1(
+        &p.RCC,
+        gpioa.p8,
+        rcc::cfgr::Mco1::PllClock,
+        rcc::cfgr::Moc1pre::Div4,
+    );
+
+    loop {}
+}
@@ -0,0 +1,79 @@
+//! Busy-wait delays timed by SysTick
+
+use core::cmp;
+
+use cortex_m::peripheral::{SystClkSource, SYST};
+use hal::blocking::delay::{DelayMs, DelayUs};
+
+use peripheral::rcc::Clocks;
+
+/// Largest SysTick reload value (24-bit counter)
+const SYST_RELOAD_MAX: u32 = 0x00FF_FFFF;
+
+/// Busy-wait delay counting the core clock (HCLK) with SysTick
+///
+/// The delay takes over SysTick: the reload value is reprogrammed on every wait and the
+/// counter is left stopped, so SysTick cannot serve as a periodic tick at the same time.
+pub struct Delay {
+    hclk: u32,
+}
+
+impl Delay {
+    /// Stops SysTick and makes it count the core clock of `clocks`
+    ///
+    /// SysTick is taken over without being owned: its interrupt is disabled, and
+    /// nothing keeps other code from reprogramming it while the delay is in use.
+    pub fn new(clocks: &Clocks) -> Delay {
+        let syst = unsafe { &*SYST.get() };
+        syst.disable_counter();
+        syst.disable_interrupt();
+        syst.set_clock_source(SystClkSource::Core);
+        Delay { hclk: clocks.hclk() }
+    }
+
+    /// Waits for `us` microseconds
+    pub fn delay_us(&mut self, us: u32) {
+        let ticks = self.hclk as u64 * us as u64 / 1_000_000;
+        self.wait(ticks);
+    }
+
+    /// Waits for `ms` milliseconds
+    pub fn delay_ms(&mut self, ms: u32) {
+        let ticks = self.hclk as u64 * ms as u64 / 1_000;
+        self.wait(ticks);
+    }
+
+    fn wait(&mut self, mut ticks: u64) {
+        let syst = unsafe { &*SYST.get() };
+        // A reload value of 0 never wraps, so a single remaining tick is dropped
+        while ticks > 1 {
+            let chunk = cmp::min(ticks, SYST_RELOAD_MAX as u64 + 1) as u32;
+            syst.set_reload(chunk - 1);
+            syst.clear_current();
+            syst.enable_counter();
+            while !syst.has_wrapped() {}
+            syst.disable_counter();
+            ticks -= chunk as u64;
+        }
+    }
+}
+
+macro_rules! delay_impls {
+    ($($T:ty,)+) => {
+        $(
+            impl DelayMs<$T> for Delay {
+                fn delay_ms(&mut self, ms: $T) {
+                    Delay::delay_ms(self, ms as u32)
+                }
+            }
+
+            impl DelayUs<$T> for Delay {
+                fn delay_us(&mut self, us: $T) {
+                    Delay::delay_us(self, us as u32)
+                }
+            }
+        )+
+    }
+}
+
+delay_impls!(u8, u16, u32,);
@@ -0,0 +1,595 @@
+//! Device interrupts of the STM32F401 and NVIC helpers
+//!
+//! Every IRQ handler is a weak symbol that defaults to `default_irq_handler`. An application
+//! handles an interrupt by defining a function with the same name:
+//!
+//! ``` ignore
+//! #[no_mangle]
+//! pub extern "C" fn EXTI15_10() {
+//!     // ...
+//! }
+//! ```
+
+use cortex_m::asm;
+use cortex_m::interrupt::Nr;
+use cortex_m::peripheral::{NVIC, SCB};
+
+/// Device interrupt numbers of the STM32F401
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    /// Window watchdog
+    WWDG = 0,
+    /// PVD through EXTI line 16
+    PVD = 1,
+    /// Tamper and timestamp through EXTI line 21
+    TAMP_STAMP = 2,
+    /// RTC wakeup through EXTI line 22
+    RTC_WKUP = 3,
+    /// Flash global
+    FLASH = 4,
+    /// RCC global
+    RCC = 5,
+    /// EXTI line 0
+    EXTI0 = 6,
+    /// EXTI line 1
+    EXTI1 = 7,
+    /// EXTI line 2
+    EXTI2 = 8,
+    /// EXTI line 3
+    EXTI3 = 9,
+    /// EXTI line 4
+    EXTI4 = 10,
+    /// DMA1 stream 0
+    DMA1_STREAM0 = 11,
+    /// DMA1 stream 1
+    DMA1_STREAM1 = 12,
+    /// DMA1 stream 2
+    DMA1_STREAM2 = 13,
+    /// DMA1 stream 3
+    DMA1_STREAM3 = 14,
+    /// DMA1 stream 4
+    DMA1_STREAM4 = 15,
+    /// DMA1 stream 5
+    DMA1_STREAM5 = 16,
+    /// DMA1 stream 6
+    DMA1_STREAM6 = 17,
+    /// ADC1 global
+    ADC = 18,
+    /// EXTI lines 5 to 9
+    EXTI9_5 = 23,
+    /// TIM1 break and TIM9 global
+    TIM1_BRK_TIM9 = 24,
+    /// TIM1 update and TIM10 global
+    TIM1_UP_TIM10 = 25,
+    /// TIM1 trigger, commutation and TIM11 global
+    TIM1_TRG_COM_TIM11 = 26,
+    /// TIM1 capture compare
+    TIM1_CC = 27,
+    /// TIM2 global
+    TIM2 = 28,
+    /// TIM3 global
+    TIM3 = 29,
+    /// TIM4 global
+    TIM4 = 30,
+    /// I2C1 event
+    I2C1_EV = 31,
+    /// I2C1 error
+    I2C1_ER = 32,
+    /// I2C2 event
+    I2C2_EV = 33,
+    /// I2C2 error
+    I2C2_ER = 34,
+    /// SPI1 global
+    SPI1 = 35,
+    /// SPI2 global
+    SPI2 = 36,
+    /// USART1 global
+    USART1 = 37,
+    /// USART2 global
+    USART2 = 38,
+    /// EXTI lines 10 to 15
+    EXTI15_10 = 40,
+    /// RTC alarms through EXTI line 17
+    RTC_ALARM = 41,
+    /// USB OTG FS wakeup through EXTI line 18
+    OTG_FS_WKUP = 42,
+    /// DMA1 stream 7
+    DMA1_STREAM7 = 47,
+    /// SDIO global
+    SDIO = 49,
+    /// TIM5 global
+    TIM5 = 50,
+    /// SPI3 global
+    SPI3 = 51,
+    /// DMA2 stream 0
+    DMA2_STREAM0 = 56,
+    /// DMA2 stream 1
+    DMA2_STREAM1 = 57,
+    /// DMA2 stream 2
+    DMA2_STREAM2 = 58,
+    /// DMA2 stream 3
+    DMA2_STREAM3 = 59,
+    /// DMA2 stream 4
+    DMA2_STREAM4 = 60,
+    /// USB OTG FS global
+    OTG_FS = 67,
+    /// DMA2 stream 5
+    DMA2_STREAM5 = 68,
+    /// DMA2 stream 6
+    DMA2_STREAM6 = 69,
+    /// DMA2 stream 7
+    DMA2_STREAM7 = 70,
+    /// USART6 global
+    USART6 = 71,
+    /// I2C3 event
+    I2C3_EV = 72,
+    /// I2C3 error
+    I2C3_ER = 73,
+    /// FPU global
+    FPU = 81,
+    /// SPI4 global
+    SPI4 = 84,
+}
+
+global_asm!(
+    ".weak WWDG
+    WWDG = default_irq_handler
+    .weak PVD
+    PVD = default_irq_handler
+    .weak TAMP_STAMP
+    TAMP_STAMP = default_irq_handler
+    .weak RTC_WKUP
+    RTC_WKUP = default_irq_handler
+    .weak FLASH
+    FLASH = default_irq_handler
+    .weak RCC
+    RCC = default_irq_handler
+    .weak EXTI0
+    EXTI0 = default_irq_handler
+    .weak EXTI1
+    EXTI1 = default_irq_handler
+    .weak EXTI2
+    EXTI2 = default_irq_handler
+    .weak EXTI3
+    EXTI3 = default_irq_handler
+    .weak EXTI4
+    EXTI4 = default_irq_handler
+    .weak DMA1_STREAM0
+    DMA1_STREAM0 = default_irq_handler
+    .weak DMA1_STREAM1
+    DMA1_STREAM1 = default_irq_handler
+    .weak DMA1_STREAM2
+    DMA1_STREAM2 = default_irq_handler
+    .weak DMA1_STREAM3
+    DMA1_STREAM3 = default_irq_handler
+    .weak DMA1_STREAM4
+    DMA1_STREAM4 = default_irq_handler
+    .weak DMA1_STREAM5
+    DMA1_STREAM5 = default_irq_handler
+    .weak DMA1_STREAM6
+    DMA1_STREAM6 = default_irq_handler
+    .weak ADC
+    ADC = default_irq_handler
+    .weak EXTI9_5
+    EXTI9_5 = default_irq_handler
+    .weak TIM1_BRK_TIM9
+    TIM1_BRK_TIM9 = default_irq_handler
+    .weak TIM1_UP_TIM10
+    TIM1_UP_TIM10 = default_irq_handler
+    .weak TIM1_TRG_COM_TIM11
+    TIM1_TRG_COM_TIM11 = default_irq_handler
+    .weak TIM1_CC
+    TIM1_CC = default_irq_handler
+    .weak TIM2
+    TIM2 = default_irq_handler
+    .weak TIM3
+    TIM3 = default_irq_handler
+    .weak TIM4
+    TIM4 = default_irq_handler
+    .weak I2C1_EV
+    I2C1_EV = default_irq_handler
+    .weak I2C1_ER
+    I2C1_ER = default_irq_handler
+    .weak I2C2_EV
+    I2C2_EV = default_irq_handler
+    .weak I2C2_ER
+    I2C2_ER = default_irq_handler
+    .weak SPI1
+    SPI1 = default_irq_handler
+    .weak SPI2
+    SPI2 = default_irq_handler
+    .weak USART1
+    USART1 = default_irq_handler
+    .weak USART2
+    USART2 = default_irq_handler
+    .weak EXTI15_10
+    EXTI15_10 = default_irq_handler
+    .weak RTC_ALARM
+    RTC_ALARM = default_irq_handler
+    .weak OTG_FS_WKUP
+    OTG_FS_WKUP = default_irq_handler
+    .weak DMA1_STREAM7
+    DMA1_STREAM7 = default_irq_handler
+    .weak SDIO
+    SDIO = default_irq_handler
+    .weak TIM5
+    TIM5 = default_irq_handler
+    .weak SPI3
+    SPI3 = default_irq_handler
+    .weak DMA2_STREAM0
+    DMA2_STREAM0 = default_irq_handler
+    .weak DMA2_STREAM1
+    DMA2_STREAM1 = default_irq_handler
+    .weak DMA2_STREAM2
+    DMA2_STREAM2 = default_irq_handler
+    .weak DMA2_STREAM3
+    DMA2_STREAM3 = default_irq_handler
+    .weak DMA2_STREAM4
+    DMA2_STREAM4 = default_irq_handler
+    .weak OTG_FS
+    OTG_FS = default_irq_handler
+    .weak DMA2_STREAM5
+    DMA2_STREAM5 = default_irq_handler
+    .weak DMA2_STREAM6
+    DMA2_STREAM6 = default_irq_handler
+    .weak DMA2_STREAM7
+    DMA2_STREAM7 = default_irq_handler
+    .weak USART6
+    USART6 = default_irq_handler
+    .weak I2C3_EV
+    I2C3_EV = default_irq_handler
+    .weak I2C3_ER
+    I2C3_ER = default_irq_handler
+    .weak FPU
+    FPU = default_irq_handler
+    .weak SPI4
+    SPI4 = default_irq_handler"
+);
+
+#[allow(non_snake_case)]
+extern "C" {
+    fn WWDG();
+    fn PVD();
+    fn TAMP_STAMP();
+    fn RTC_WKUP();
+    fn FLASH();
+    fn RCC();
+    fn EXTI0();
+    fn EXTI1();
+    fn EXTI2();
+    fn EXTI3();
+    fn EXTI4();
+    fn DMA1_STREAM0();
+    fn DMA1_STREAM1();
+    fn DMA1_STREAM2();
+    fn DMA1_STREAM3();
+    fn DMA1_STREAM4();
+    fn DMA1_STREAM5();
+    fn DMA1_STREAM6();
+    fn ADC();
+    fn EXTI9_5();
+    fn TIM1_BRK_TIM9();
+    fn TIM1_UP_TIM10();
+    fn TIM1_TRG_COM_TIM11();
+    fn TIM1_CC();
+    fn TIM2();
+    fn TIM3();
+    fn TIM4();
+    fn I2C1_EV();
+    fn I2C1_ER();
+    fn I2C2_EV();
+    fn I2C2_ER();
+    fn SPI1();
+    fn SPI2();
+    fn USART1();
+    fn USART2();
+    fn EXTI15_10();
+    fn RTC_ALARM();
+    fn OTG_FS_WKUP();
+    fn DMA1_STREAM7();
+    fn SDIO();
+    fn TIM5();
+    fn SPI3();
+    fn DMA2_STREAM0();
+    fn DMA2_STREAM1();
+    fn DMA2_STREAM2();
+    fn DMA2_STREAM3();
+    fn DMA2_STREAM4();
+    fn OTG_FS();
+    fn DMA2_STREAM5();
+    fn DMA2_STREAM6();
+    fn DMA2_STREAM7();
+    fn USART6();
+    fn I2C3_EV();
+    fn I2C3_ER();
+    fn FPU();
+    fn SPI4();
+}
+
+/// Vector table entries of the 85 device interrupts, `None` marks a reserved position
+#[used]
+#[link_section = ".vector_table.interrupts"]
+static INTERRUPTS: [Option<unsafe extern "C" fn()>; 85] = [
+    Some(WWDG),
+    Some(PVD),
+    Some(TAMP_STAMP),
+    Some(RTC_WKUP),
+    Some(FLASH),
+    Some(RCC),
+    Some(EXTI0),
+    Some(EXTI1),
+    Some(EXTI2),
+    Some(EXTI3),
+    Some(EXTI4),
+    Some(DMA1_STREAM0),
+    Some(DMA1_STREAM1),
+    Some(DMA1_STREAM2),
+    Some(DMA1_STREAM3),
+    Some(DMA1_STREAM4),
+    Some(DMA1_STREAM5),
+    Some(DMA1_STREAM6),
+    Some(ADC),
+    None,
+    None,
+    None,
+    None,
+    Some(EXTI9_5),
+    Some(TIM1_BRK_TIM9),
+    Some(TIM1_UP_TIM10),
+    Some(TIM1_TRG_COM_TIM11),
+    Some(TIM1_CC),
+    Some(TIM2),
+    Some(TIM3),
+    Some(TIM4),
+    Some(I2C1_EV),
+    Some(I2C1_ER),
+    Some(I2C2_EV),
+    Some(I2C2_ER),
+    Some(SPI1),
+    Some(SPI2),
+    Some(USART1),
+    Some(USART2),
+    None,
+    Some(EXTI15_10),
+    Some(RTC_ALARM),
+    Some(OTG_FS_WKUP),
+    None,
+    None,
+    None,
+    None,
+    Some(DMA1_STREAM7),
+    None,
+    Some(SDIO),
+    Some(TIM5),
+    Some(SPI3),
+    None,
+    None,
+    None,
+    None,
+    Some(DMA2_STREAM0),
+    Some(DMA2_STREAM1),
+    Some(DMA2_STREAM2),
+    Some(DMA2_STREAM3),
+    Some(DMA2_STREAM4),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some(OTG_FS),
+    Some(DMA2_STREAM5),
+    Some(DMA2_STREAM6),
+    Some(DMA2_STREAM7),
+    Some(USART6),
+    Some(I2C3_EV),
+    Some(I2C3_ER),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some(FPU),
+    None,
+    None,
+    Some(SPI4),
+];
+
+/// IRQ number of the last interrupt that reached `default_irq_handler`
+///
+/// It is kept in a static so it can be inspected from the debugger after the breakpoint.
+#[no_mangle]
+pub static mut UNHANDLED_IRQ: i16 = -1;
+
+/// Handler of every interrupt the application does not handle itself
+#[no_mangle]
+pub extern "C" fn default_irq_handler() {
+    // VECTACTIVE holds the exception number, device interrupts start at 16
+    const VECTACTIVE_MASK: u32 = 0x1FF;
+    let irqn = (unsafe { (*SCB.get()).icsr.read() } & VECTACTIVE_MASK) as i16 - 16;
+    unsafe {
+        UNHANDLED_IRQ = irqn;
+    }
+    asm::bkpt();
+}
+
+/// Cortex-M4 system exceptions whose priority is configurable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exception {
+    MemoryManagement = -12,
+    BusFault = -11,
+    UsageFault = -10,
+    SVCall = -5,
+    DebugMonitor = -4,
+    PendSV = -2,
+    SysTick = -1,
+}
+
+/// CMSIS style IRQ number, negative for system exceptions
+pub trait IrqNumber {
+    fn irqn(&self) -> i16;
+}
+
+impl IrqNumber for Interrupt {
+    fn irqn(&self) -> i16 {
+        *self as i16
+    }
+}
+
+impl IrqNumber for Exception {
+    fn irqn(&self) -> i16 {
+        *self as i16
+    }
+}
+
+unsafe impl Nr for Interrupt {
+    fn nr(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// Number of priority bits implemented by the STM32F4 NVIC
+pub const NVIC_PRIO_BITS: u32 = 4;
+
+/// 0 bits for pre-emption priority, 4 bits for subpriority
+pub const PRIORITYGROUP_0: u32 = 0b111;
+/// 1 bit for pre-emption priority, 3 bits for subpriority
+pub const PRIORITYGROUP_1: u32 = 0b110;
+/// 2 bits for pre-emption priority, 2 bits for subpriority
+pub const PRIORITYGROUP_2: u32 = 0b101;
+/// 3 bits for pre-emption priority, 1 bit for subpriority
+pub const PRIORITYGROUP_3: u32 = 0b100;
+/// 4 bits for pre-emption priority, 0 bits for subpriority
+pub const PRIORITYGROUP_4: u32 = 0b011;
+
+const AIRCR_VECTKEY: u32 = 0x5FA << 16;
+const AIRCR_VECTKEY_MASK: u32 = 0xFFFF << 16;
+const AIRCR_PRIGROUP_SHIFT: u32 = 8;
+const AIRCR_PRIGROUP_MASK: u32 = 0b111 << AIRCR_PRIGROUP_SHIFT;
+
+/// Enables `irq` in the NVIC
+pub fn enable(irq: Interrupt) {
+    let (reg, bit) = bit_position(irq);
+    unsafe { (*NVIC.get()).iser[reg].write(bit) }
+}
+
+/// Disables `irq` in the NVIC
+pub fn disable(irq: Interrupt) {
+    let (reg, bit) = bit_position(irq);
+    unsafe { (*NVIC.get()).icer[reg].write(bit) }
+}
+
+/// Returns true if `irq` is enabled in the NVIC
+pub fn is_enabled(irq: Interrupt) -> bool {
+    let (reg, bit) = bit_position(irq);
+    unsafe { (*NVIC.get()).iser[reg].read() & bit != 0 }
+}
+
+/// Sets the pending state of `irq`
+pub fn pend(irq: Interrupt) {
+    let (reg, bit) = bit_position(irq);
+    unsafe { (*NVIC.get()).ispr[reg].write(bit) }
+}
+
+/// Clears the pending state of `irq`
+pub fn unpend(irq: Interrupt) {
+    let (reg, bit) = bit_position(irq);
+    unsafe { (*NVIC.get()).icpr[reg].write(bit) }
+}
+
+/// Returns true if `irq` is pending
+pub fn is_pending(irq: Interrupt) -> bool {
+    let (reg, bit) = bit_position(irq);
+    unsafe { (*NVIC.get()).ispr[reg].read() & bit != 0 }
+}
+
+/// Returns true if the handler of `irq` is running or pre-empted
+pub fn is_active(irq: Interrupt) -> bool {
+    let (reg, bit) = bit_position(irq);
+    unsafe { (*NVIC.get()).iabr[reg].read() & bit != 0 }
+}
+
+/// Selects how the priority bits are split into pre-emption priority and subpriority
+pub fn set_priority_grouping(priority_group: u32) {
+    unsafe {
+        (*SCB.get()).aircr.modify(|v| {
+            (v & !(AIRCR_VECTKEY_MASK | AIRCR_PRIGROUP_MASK)) | AIRCR_VECTKEY |
+                ((priority_group & 0b111) << AIRCR_PRIGROUP_SHIFT)
+        });
+    }
+}
+
+/// Returns the current priority grouping
+pub fn get_priority_grouping() -> u32 {
+    (unsafe { (*SCB.get()).aircr.read() } & AIRCR_PRIGROUP_MASK) >> AIRCR_PRIGROUP_SHIFT
+}
+
+/// Combines a pre-emption priority and a subpriority into a priority for `priority_group`
+pub fn encode_priority(priority_group: u32, preempt_priority: u32, sub_priority: u32) -> u32 {
+    let (preempt_bits, sub_bits) = priority_bits(priority_group);
+    (preempt_priority & ((1 << preempt_bits) - 1)) << sub_bits |
+        (sub_priority & ((1 << sub_bits) - 1))
+}
+
+/// Splits `priority` into its pre-emption priority and subpriority for `priority_group`
+pub fn decode_priority(priority: u32, priority_group: u32) -> (u32, u32) {
+    let (preempt_bits, sub_bits) = priority_bits(priority_group);
+    (
+        (priority >> sub_bits) & ((1 << preempt_bits) - 1),
+        priority & ((1 << sub_bits) - 1),
+    )
+}
+
+/// Sets the priority of an interrupt or system exception, lower values are more urgent
+pub fn set_priority<I: IrqNumber>(irq: I, priority: u32) {
+    let irqn = irq.irqn();
+    let value = ((priority << (8 - NVIC_PRIO_BITS)) & 0xFF) as u8;
+    unsafe {
+        if irqn < 0 {
+            (*SCB.get()).shpr[system_handler_index(irqn)].write(value);
+        } else {
+            (*NVIC.get()).ipr[irqn as usize].write(value);
+        }
+    }
+}
+
+/// Returns the priority of an interrupt or system exception
+pub fn get_priority<I: IrqNumber>(irq: I) -> u32 {
+    let irqn = irq.irqn();
+    let value = unsafe {
+        if irqn < 0 {
+            (*SCB.get()).shpr[system_handler_index(irqn)].read()
+        } else {
+            (*NVIC.get()).ipr[irqn as usize].read()
+        }
+    };
+    (value as u32) >> (8 - NVIC_PRIO_BITS)
+}
+
+fn bit_position(irq: Interrupt) -> (usize, u32) {
+    let nr = irq as usize;
+    (nr / 32, 1 << (nr % 32))
+}
+
+fn system_handler_index(irqn: i16) -> usize {
+    // SHPR1 starts with the priority of exception 4 (MemoryManagement)
+    (irqn + 16 - 4) as usize
+}
+
+fn priority_bits(priority_group: u32) -> (u32, u32) {
+    let group = priority_group & 0b111;
+    let preempt_bits = if 7 - group > NVIC_PRIO_BITS {
+        NVIC_PRIO_BITS
+    } else {
+        7 - group
+    };
+    let sub_bits = if group + NVIC_PRIO_BITS < 7 {
+        0
+    } else {
+        group + NVIC_PRIO_BITS - 7
+    };
+    (preempt_bits, sub_bits)
+}
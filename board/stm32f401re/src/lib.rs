@@ -1,27 +1,23 @@
 #![no_std]
 #![feature(asm)]
+#![feature(global_asm)]
 #![feature(used)]
 
 extern crate cortex_m;
 extern crate cortex_m_rt;
+extern crate embedded_hal as hal;
 extern crate volatile_register;
 
-use cortex_m::asm;
-
+pub mod delay;
+pub mod interrupt;
 pub mod peripheral;
 
+/// Spins for `ticks` loop iterations
+///
+/// The duration depends on SYSCLK and on the optimization level; use `delay::Delay` for
+/// a delay in time units.
 pub fn delay(ticks: u32) {
     for _ in 1..ticks {
         unsafe { asm!("") }
     }
 }
-
-// As we are not using interrupts, we just register a dummy catch all handler
-#[allow(dead_code)]
-#[used]
-#[link_section = ".vector_table.interrupts"]
-static INTERRUPTS: [extern "C" fn(); 240] = [default_handler; 240];
-
-extern "C" fn default_handler() {
-    asm::bkpt();
-}
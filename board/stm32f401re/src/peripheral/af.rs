@@ -0,0 +1,263 @@
+//! Alternate function pin mapping of the STM32F401RE
+//!
+//! Every (pin, signal, alternate function) combination of the datasheet is encoded as
+//! an implementation of a signal trait for the pin in the matching `Alternate` mode, so a
+//! driver asking for e.g. `SckPin<SPI3>` only accepts a pin that can carry SPI3_SCK.
+//!
+//! Only the pins bonded out on the LQFP64 package of the F401RE are listed: ports A to C
+//! except PB11 (VCAP1 on this package), PD2, PH0 and PH1. SPI4 has no pins on this
+//! package, so no pin implements its signals.
+
+use peripheral::gpio::*;
+use peripheral::spi::{SPI1, SPI2, SPI3};
+use peripheral::{I2C1, I2C2, I2C3, OTG_FS, SDIO, TIM1, TIM2, TIM3, TIM4, TIM5, TIM9, TIM10, TIM11};
+use peripheral::{USART1, USART2, USART6};
+
+/// Microcontroller clock output 1 (type state)
+pub struct MCO1;
+
+/// Microcontroller clock output 2 (type state)
+pub struct MCO2;
+
+macro_rules! signals {
+    ($($(#[$attr:meta])* $Signal:ident,)+) => {
+        $(
+            $(#[$attr])*
+            pub unsafe trait $Signal<INSTANCE> {}
+        )+
+    }
+}
+
+signals!(
+    /// SPI slave select
+    NssPin,
+    /// SPI clock
+    SckPin,
+    /// SPI master in / slave out
+    MisoPin,
+    /// SPI master out / slave in
+    MosiPin,
+    /// I2S word select
+    WsPin,
+    /// I2S, USART or SDIO clock
+    CkPin,
+    /// I2S serial data
+    SdPin,
+    /// I2S full duplex extension serial data
+    ExtSdPin,
+    /// I2S master clock output
+    MckPin,
+    /// I2S external clock input
+    CkinPin,
+    /// USART transmit
+    TxPin,
+    /// USART receive
+    RxPin,
+    /// USART clear to send
+    CtsPin,
+    /// USART request to send
+    RtsPin,
+    /// I2C clock
+    SclPin,
+    /// I2C data
+    SdaPin,
+    /// I2C SMBus alert
+    SmbaPin,
+    /// Timer channel 1
+    Ch1Pin,
+    /// Timer channel 2
+    Ch2Pin,
+    /// Timer channel 3
+    Ch3Pin,
+    /// Timer channel 4
+    Ch4Pin,
+    /// Timer complementary channel 1
+    Ch1NPin,
+    /// Timer complementary channel 2
+    Ch2NPin,
+    /// Timer complementary channel 3
+    Ch3NPin,
+    /// Timer external trigger
+    EtrPin,
+    /// Timer break input
+    BkinPin,
+    /// USB OTG FS data minus
+    DmPin,
+    /// USB OTG FS data plus
+    DpPin,
+    /// USB OTG FS ID
+    IdPin,
+    /// USB OTG FS VBUS sensing
+    VbusPin,
+    /// USB OTG FS start of frame
+    SofPin,
+    /// SDIO command
+    CmdPin,
+    /// SDIO data 0
+    D0Pin,
+    /// SDIO data 1
+    D1Pin,
+    /// SDIO data 2
+    D2Pin,
+    /// SDIO data 3
+    D3Pin,
+    /// SDIO data 4
+    D4Pin,
+    /// SDIO data 5
+    D5Pin,
+    /// SDIO data 6
+    D6Pin,
+    /// SDIO data 7
+    D7Pin,
+    /// Microcontroller clock output
+    McoPin,
+);
+
+/// The I2S external clock input feeds the I2S clock generator of every instance
+#[allow(non_camel_case_types)]
+pub struct I2S_CKIN;
+
+macro_rules! af_pin {
+    ($PXi:ident: $AF:ident => $($Signal:path),+) => {
+        $(
+            unsafe impl $Signal for $PXi<Alternate<$AF>> {}
+        )+
+    }
+}
+
+macro_rules! af_table {
+    ($($PXi:ident: $AF:ident => [$($Signal:path),+],)+) => {
+        $(
+            af_pin!($PXi: $AF => $($Signal),+);
+        )+
+    }
+}
+
+// Table 9 "Alternate function mapping" of the STM32F401xD/E datasheet, LQFP64 pins only.
+// SPI2 and SPI3 double as I2S2 and I2S3, so their I2S signals use the SPI instances.
+af_table!(
+    PA0: AF1 => [Ch1Pin<TIM2>, EtrPin<TIM2>],
+    PA0: AF2 => [Ch1Pin<TIM5>],
+    PA0: AF7 => [CtsPin<USART2>],
+    PA1: AF1 => [Ch2Pin<TIM2>],
+    PA1: AF2 => [Ch2Pin<TIM5>],
+    PA1: AF7 => [RtsPin<USART2>],
+    PA2: AF1 => [Ch3Pin<TIM2>],
+    PA2: AF2 => [Ch3Pin<TIM5>],
+    PA2: AF3 => [Ch1Pin<TIM9>],
+    PA2: AF7 => [TxPin<USART2>],
+    PA3: AF1 => [Ch4Pin<TIM2>],
+    PA3: AF2 => [Ch4Pin<TIM5>],
+    PA3: AF3 => [Ch2Pin<TIM9>],
+    PA3: AF7 => [RxPin<USART2>],
+    PA4: AF5 => [NssPin<SPI1>],
+    PA4: AF6 => [NssPin<SPI3>, WsPin<SPI3>],
+    PA4: AF7 => [CkPin<USART2>],
+    PA5: AF1 => [Ch1Pin<TIM2>, EtrPin<TIM2>],
+    PA5: AF5 => [SckPin<SPI1>],
+    PA6: AF1 => [BkinPin<TIM1>],
+    PA6: AF2 => [Ch1Pin<TIM3>],
+    PA6: AF5 => [MisoPin<SPI1>],
+    PA7: AF1 => [Ch1NPin<TIM1>],
+    PA7: AF2 => [Ch2Pin<TIM3>],
+    PA7: AF5 => [MosiPin<SPI1>],
+    PA8: AF0 => [McoPin<MCO1>],
+    PA8: AF1 => [Ch1Pin<TIM1>],
+    PA8: AF4 => [SclPin<I2C3>],
+    PA8: AF7 => [CkPin<USART1>],
+    PA8: AF10 => [SofPin<OTG_FS>],
+    PA9: AF1 => [Ch2Pin<TIM1>],
+    PA9: AF4 => [SmbaPin<I2C3>],
+    PA9: AF7 => [TxPin<USART1>],
+    PA9: AF10 => [VbusPin<OTG_FS>],
+    PA10: AF1 => [Ch3Pin<TIM1>],
+    PA10: AF7 => [RxPin<USART1>],
+    PA10: AF10 => [IdPin<OTG_FS>],
+    PA11: AF1 => [Ch4Pin<TIM1>],
+    PA11: AF7 => [CtsPin<USART1>],
+    PA11: AF8 => [TxPin<USART6>],
+    PA11: AF10 => [DmPin<OTG_FS>],
+    PA12: AF1 => [EtrPin<TIM1>],
+    PA12: AF7 => [RtsPin<USART1>],
+    PA12: AF8 => [RxPin<USART6>],
+    PA12: AF10 => [DpPin<OTG_FS>],
+    PA15: AF1 => [Ch1Pin<TIM2>, EtrPin<TIM2>],
+    PA15: AF5 => [NssPin<SPI1>],
+    PA15: AF6 => [NssPin<SPI3>, WsPin<SPI3>],
+
+    PB0: AF1 => [Ch2NPin<TIM1>],
+    PB0: AF2 => [Ch3Pin<TIM3>],
+    PB1: AF1 => [Ch3NPin<TIM1>],
+    PB1: AF2 => [Ch4Pin<TIM3>],
+    PB3: AF1 => [Ch2Pin<TIM2>],
+    PB3: AF5 => [SckPin<SPI1>],
+    PB3: AF6 => [SckPin<SPI3>, CkPin<SPI3>],
+    PB3: AF9 => [SdaPin<I2C2>],
+    PB4: AF2 => [Ch1Pin<TIM3>],
+    PB4: AF5 => [MisoPin<SPI1>],
+    PB4: AF6 => [MisoPin<SPI3>],
+    PB4: AF7 => [ExtSdPin<SPI3>],
+    PB4: AF9 => [SdaPin<I2C3>],
+    PB5: AF2 => [Ch2Pin<TIM3>],
+    PB5: AF4 => [SmbaPin<I2C1>],
+    PB5: AF5 => [MosiPin<SPI1>],
+    PB5: AF6 => [MosiPin<SPI3>, SdPin<SPI3>],
+    PB6: AF2 => [Ch1Pin<TIM4>],
+    PB6: AF4 => [SclPin<I2C1>],
+    PB6: AF7 => [TxPin<USART1>],
+    PB7: AF2 => [Ch2Pin<TIM4>],
+    PB7: AF4 => [SdaPin<I2C1>],
+    PB7: AF7 => [RxPin<USART1>],
+    PB8: AF2 => [Ch3Pin<TIM4>],
+    PB8: AF3 => [Ch1Pin<TIM10>],
+    PB8: AF4 => [SclPin<I2C1>],
+    PB8: AF12 => [D4Pin<SDIO>],
+    PB9: AF2 => [Ch4Pin<TIM4>],
+    PB9: AF3 => [Ch1Pin<TIM11>],
+    PB9: AF4 => [SdaPin<I2C1>],
+    PB9: AF5 => [NssPin<SPI2>, WsPin<SPI2>],
+    PB9: AF12 => [D5Pin<SDIO>],
+    PB10: AF1 => [Ch3Pin<TIM2>],
+    PB10: AF4 => [SclPin<I2C2>],
+    PB10: AF5 => [SckPin<SPI2>, CkPin<SPI2>],
+    PB12: AF1 => [BkinPin<TIM1>],
+    PB12: AF4 => [SmbaPin<I2C2>],
+    PB12: AF5 => [NssPin<SPI2>, WsPin<SPI2>],
+    PB13: AF1 => [Ch1NPin<TIM1>],
+    PB13: AF5 => [SckPin<SPI2>, CkPin<SPI2>],
+    PB14: AF1 => [Ch2NPin<TIM1>],
+    PB14: AF5 => [MisoPin<SPI2>],
+    PB14: AF6 => [ExtSdPin<SPI2>],
+    PB15: AF1 => [Ch3NPin<TIM1>],
+    PB15: AF5 => [MosiPin<SPI2>, SdPin<SPI2>],
+
+    PC2: AF5 => [MisoPin<SPI2>],
+    PC2: AF6 => [ExtSdPin<SPI2>],
+    PC3: AF5 => [MosiPin<SPI2>, SdPin<SPI2>],
+    PC6: AF2 => [Ch1Pin<TIM3>],
+    PC6: AF5 => [MckPin<SPI2>],
+    PC6: AF8 => [TxPin<USART6>],
+    PC6: AF12 => [D6Pin<SDIO>],
+    PC7: AF2 => [Ch2Pin<TIM3>],
+    PC7: AF6 => [MckPin<SPI3>],
+    PC7: AF8 => [RxPin<USART6>],
+    PC7: AF12 => [D7Pin<SDIO>],
+    PC8: AF2 => [Ch3Pin<TIM3>],
+    PC8: AF8 => [CkPin<USART6>],
+    PC8: AF12 => [D0Pin<SDIO>],
+    PC9: AF0 => [McoPin<MCO2>],
+    PC9: AF2 => [Ch4Pin<TIM3>],
+    PC9: AF4 => [SdaPin<I2C3>],
+    PC9: AF5 => [CkinPin<I2S_CKIN>],
+    PC9: AF12 => [D1Pin<SDIO>],
+    PC10: AF6 => [SckPin<SPI3>, CkPin<SPI3>],
+    PC10: AF12 => [D2Pin<SDIO>],
+    PC11: AF5 => [ExtSdPin<SPI3>],
+    PC11: AF6 => [MisoPin<SPI3>],
+    PC11: AF12 => [D3Pin<SDIO>],
+    PC12: AF6 => [MosiPin<SPI3>, SdPin<SPI3>],
+    PC12: AF12 => [CkPin<SDIO>],
+
+    PD2: AF2 => [EtrPin<TIM3>],
+    PD2: AF12 => [CmdPin<SDIO>],
+);
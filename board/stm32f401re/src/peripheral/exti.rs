@@ -12,6 +12,157 @@ pub struct RegisterMap {
     pub pr: RW<u32>,
 }
 
+peripheral!(
+    /// External interrupt/event controller
+    EXTI, EXTI_BASE
+);
+
+/// EXTI line connected to the PVD output
+pub const LINE_PVD: u8 = 16;
+/// EXTI line connected to the RTC alarm event
+pub const LINE_RTC_ALARM: u8 = 17;
+/// EXTI line connected to the USB OTG FS wakeup event
+pub const LINE_OTG_FS_WKUP: u8 = 18;
+/// EXTI line connected to the RTC tamper and timestamp events
+pub const LINE_RTC_TAMP_STAMP: u8 = 21;
+/// EXTI line connected to the RTC wakeup event
+pub const LINE_RTC_WKUP: u8 = 22;
+
+/// Signal edge that triggers a line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    RisingFalling,
+}
+
+/// What a line generates when it triggers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// An interrupt request to the NVIC
+    Interrupt,
+    /// An event that wakes the core from WFE
+    Event,
+}
+
+/// Driver for the edge detectors of EXTI lines 0 to 22
+///
+/// Lines 0 to 15 are the GPIO lines selected through SYSCFG, lines 19 and 20 do not exist.
+pub struct Exti {
+    exti: EXTI,
+}
+
+impl Exti {
+    /// Takes ownership of the EXTI peripheral
+    pub fn new(exti: EXTI) -> Exti {
+        Exti { exti }
+    }
+
+    /// Releases the EXTI peripheral
+    pub fn free(self) -> EXTI {
+        self.exti
+    }
+
+    /// Makes `line` generate `output` on `edge`
+    pub fn listen(&mut self, line: u8, edge: Edge, output: Output) {
+        let bit = line_bit(line);
+        let (rising, falling) = match edge {
+            Edge::Rising => (rtsr::Trx::Enable, ftsr::Trx::Disable),
+            Edge::Falling => (rtsr::Trx::Disable, ftsr::Trx::Enable),
+            Edge::RisingFalling => (rtsr::Trx::Enable, ftsr::Trx::Enable),
+        };
+        let (interrupt, event) = match output {
+            Output::Interrupt => (imr::Mrx::NotMask, emr::Mrx::Mask),
+            Output::Event => (imr::Mrx::Mask, emr::Mrx::NotMask),
+        };
+        unsafe {
+            self.exti.rtsr.modify(|v| (v & !bit) | (rising as u32) << line);
+            self.exti.ftsr.modify(|v| (v & !bit) | (falling as u32) << line);
+            self.exti.imr.modify(|v| (v & !bit) | (interrupt as u32) << line);
+            self.exti.emr.modify(|v| (v & !bit) | (event as u32) << line);
+        }
+    }
+
+    /// Stops `line` from generating interrupts and events
+    pub fn unlisten(&mut self, line: u8) {
+        let bit = line_bit(line);
+        unsafe {
+            self.exti.imr.modify(|v| v & !bit);
+            self.exti.emr.modify(|v| v & !bit);
+            self.exti.rtsr.modify(|v| v & !bit);
+            self.exti.ftsr.modify(|v| v & !bit);
+        }
+    }
+
+    /// Returns true if `line` has triggered since its pending bit was last cleared
+    pub fn is_pending(&self, line: u8) -> bool {
+        self.exti.pr.read() & line_bit(line) == (pr::Prx::Requested as u32) << line
+    }
+
+    /// Returns the pending bits of all the lines
+    pub fn pending(&self) -> u32 {
+        self.exti.pr.read()
+    }
+
+    /// Clears the pending bit of `line`
+    pub fn clear_pending(&mut self, line: u8) {
+        // PR is write-1-to-clear, a read-modify-write would clear every pending line
+        unsafe { self.exti.pr.write(line_bit(line)) }
+    }
+
+    /// Triggers `line` from software, as if its selected edge had occurred
+    pub fn trigger(&mut self, line: u8) {
+        unsafe { self.exti.swier.write((swier::Swierx::Allow as u32) << line_index(line)) }
+    }
+}
+
+/// Handle to one EXTI line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Line {
+    line: u8,
+}
+
+impl Line {
+    /// Creates a handle to `line`
+    pub fn new(line: u8) -> Line {
+        Line { line: line_index(line) }
+    }
+
+    /// Returns the line number
+    pub fn number(&self) -> u8 {
+        self.line
+    }
+
+    /// Makes the line generate `output` on `edge`
+    pub fn listen(&self, exti: &mut Exti, edge: Edge, output: Output) {
+        exti.listen(self.line, edge, output);
+    }
+
+    /// Stops the line from generating interrupts and events
+    pub fn unlisten(&self, exti: &mut Exti) {
+        exti.unlisten(self.line);
+    }
+
+    /// Returns true if the line has triggered since its pending bit was last cleared
+    pub fn is_pending(&self, exti: &Exti) -> bool {
+        exti.is_pending(self.line)
+    }
+
+    /// Clears the pending bit of the line
+    pub fn clear_pending(&self, exti: &mut Exti) {
+        exti.clear_pending(self.line);
+    }
+}
+
+fn line_index(line: u8) -> u8 {
+    assert!(line <= 22 && line != 19 && line != 20);
+    line
+}
+
+fn line_bit(line: u8) -> u32 {
+    1 << line_index(line)
+}
+
 pub mod imr {
     /// Interrupt mask on line x
     pub enum Mrx {
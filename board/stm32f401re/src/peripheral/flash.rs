@@ -12,6 +12,11 @@ pub struct RegisterMap {
     pub optcr: RW<u32>,
 }
 
+peripheral!(
+    /// Flash interface
+    FLASH, FLASH_BASE
+);
+
 pub mod acr {
     /// Data cache reset
     pub enum Dcrst {
@@ -1,4 +1,9 @@
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use cortex_m;
+use hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
 use volatile_register::{RO, WO, RW};
+use peripheral::{exti, rcc, syscfg};
 
 pub const GPIOA_BASE: u32 = 0x4002_0000;
 pub const GPIOB_BASE: u32 = 0x4002_0400;
@@ -20,9 +25,1104 @@ pub struct RegisterMap {
     pub afr: [RW<u32>; 2],
 }
 
+peripheral!(
+    /// General purpose I/O port A
+    GPIOA, GPIOA_BASE
+);
+peripheral!(
+    /// General purpose I/O port B
+    GPIOB, GPIOB_BASE
+);
+peripheral!(
+    /// General purpose I/O port C
+    GPIOC, GPIOC_BASE
+);
+peripheral!(
+    /// General purpose I/O port D
+    GPIOD, GPIOD_BASE
+);
+peripheral!(
+    /// General purpose I/O port E
+    GPIOE, GPIOE_BASE
+);
+peripheral!(
+    /// General purpose I/O port H
+    GPIOH, GPIOH_BASE
+);
+
+/// A GPIO port that can be split into individually owned pins
+pub trait PortId {
+    /// Runtime name of the port
+    const NAME: PortName;
+    /// Base address of the port register block
+    const BASE: u32;
+    /// EXTI source selection of the port in SYSCFG EXTICRx
+    const EXTI_SOURCE: syscfg::exticrx::Extix;
+}
+
+impl PortId for GPIOA {
+    const NAME: PortName = PortName::A;
+    const BASE: u32 = GPIOA_BASE;
+    const EXTI_SOURCE: syscfg::exticrx::Extix = syscfg::exticrx::Extix::PAxPin;
+}
+
+impl PortId for GPIOB {
+    const NAME: PortName = PortName::B;
+    const BASE: u32 = GPIOB_BASE;
+    const EXTI_SOURCE: syscfg::exticrx::Extix = syscfg::exticrx::Extix::PBxPin;
+}
+
+impl PortId for GPIOC {
+    const NAME: PortName = PortName::C;
+    const BASE: u32 = GPIOC_BASE;
+    const EXTI_SOURCE: syscfg::exticrx::Extix = syscfg::exticrx::Extix::PCxPin;
+}
+
+impl PortId for GPIOD {
+    const NAME: PortName = PortName::D;
+    const BASE: u32 = GPIOD_BASE;
+    const EXTI_SOURCE: syscfg::exticrx::Extix = syscfg::exticrx::Extix::PDxPin;
+}
+
+impl PortId for GPIOE {
+    const NAME: PortName = PortName::E;
+    const BASE: u32 = GPIOE_BASE;
+    const EXTI_SOURCE: syscfg::exticrx::Extix = syscfg::exticrx::Extix::PExPin;
+}
+
+impl PortId for GPIOH {
+    const NAME: PortName = PortName::H;
+    const BASE: u32 = GPIOH_BASE;
+    const EXTI_SOURCE: syscfg::exticrx::Extix = syscfg::exticrx::Extix::PHxPin;
+}
+
+/// The position of a pin within its port
+pub trait PinIndex {
+    const INDEX: u32;
+}
+
+/// Name of a GPIO port, for selecting ports at runtime
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PortName {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+}
+
+impl PortName {
+    /// Returns the base address of the port register block
+    pub fn base(&self) -> u32 {
+        match *self {
+            PortName::A => GPIOA_BASE,
+            PortName::B => GPIOB_BASE,
+            PortName::C => GPIOC_BASE,
+            PortName::D => GPIOD_BASE,
+            PortName::E => GPIOE_BASE,
+            PortName::H => GPIOH_BASE,
+        }
+    }
+}
+
+/// Port wide access to the pins of `PORT`
+pub struct Port<PORT> {
+    _port: PhantomData<PORT>,
+}
+
+impl<PORT: PortId> Port<PORT> {
+    fn regs(&self) -> &RegisterMap {
+        unsafe { &*(PORT::BASE as *const RegisterMap) }
+    }
+
+    /// Drives the pins in `set` high and the pins in `clear` low with a single BSRR store
+    ///
+    /// Pins in neither mask are left untouched. A pin in both masks is driven high.
+    pub fn write_masked(&self, set: u16, clear: u16) {
+        unsafe { self.regs().bsrr.write(set as u32 | (clear as u32) << 16) }
+    }
+
+    /// Returns the input level of every pin of the port
+    pub fn read(&self) -> u16 {
+        self.regs().idr.read() as u16
+    }
+
+    /// Returns the output level of every pin of the port
+    pub fn read_output(&self) -> u16 {
+        self.regs().odr.read() as u16
+    }
+
+    /// Freezes the configuration of `pins` until the next reset
+    ///
+    /// `pins` is a pin or a tuple of pins of this port, which are handed back as
+    /// `LockedPin`s that can no longer be reconfigured. The lock key can only be
+    /// applied once per port and reset, so every pin that has to be locked must be
+    /// passed in the same call. On failure the pins are handed back unchanged.
+    pub fn lock<PINS>(&mut self, pins: PINS) -> Result<PINS::Locked, (PINS, LockError)>
+    where
+        PINS: Lock<PORT>,
+    {
+        let regs = self.regs();
+        if regs.lckr.read() & lckr::LCKK != 0 {
+            return Err((pins, LockError::AlreadyLocked));
+        }
+
+        let mask = pins.mask();
+
+        let key = lckr::LCKK | mask as u32;
+        cortex_m::interrupt::free(|_| unsafe {
+            regs.lckr.write(key);
+            regs.lckr.write(mask as u32);
+            regs.lckr.write(key);
+            let _ = regs.lckr.read();
+        });
+
+        if regs.lckr.read() & lckr::LCKK != 0 {
+            Ok(pins.into_locked(LockToken(())))
+        } else {
+            Err((pins, LockError::NotLatched))
+        }
+    }
+
+    /// Returns the pins whose configuration is locked
+    pub fn locked(&self) -> u16 {
+        let bits = self.regs().lckr.read();
+        if bits & lckr::LCKK != 0 {
+            bits as u16
+        } else {
+            0
+        }
+    }
+}
+
+/// A pin or a tuple of pins of `PORT` that `Port::lock` locks together
+pub trait Lock<PORT> {
+    /// The locked form of the pins
+    type Locked;
+    /// Returns the pins as a mask of the port
+    fn mask(&self) -> u16;
+    /// Converts the pins once the lock has latched
+    fn into_locked(self, token: LockToken) -> Self::Locked;
+}
+
+/// Proof that the lock sequence latched, only created by `Port::lock`
+pub struct LockToken(());
+
+impl<PORT: PortId, N: PinIndex, MODE> Lock<PORT> for Pin<PORT, N, MODE> {
+    type Locked = LockedPin<PORT, N, MODE>;
+
+    fn mask(&self) -> u16 {
+        1 << N::INDEX
+    }
+
+    fn into_locked(self, _token: LockToken) -> LockedPin<PORT, N, MODE> {
+        LockedPin { pin: self }
+    }
+}
+
+macro_rules! lock_tuples {
+    ($(($($PINS:ident: $i:tt),+),)+) => {
+        $(
+            impl<PORT, $($PINS: Lock<PORT>),+> Lock<PORT> for ($($PINS,)+) {
+                type Locked = ($($PINS::Locked,)+);
+
+                fn mask(&self) -> u16 {
+                    0 $(| self.$i.mask())+
+                }
+
+                fn into_locked(self, _token: LockToken) -> Self::Locked {
+                    ($(self.$i.into_locked(LockToken(())),)+)
+                }
+            }
+        )+
+    }
+}
+
+lock_tuples!(
+    (A: 0, B: 1),
+    (A: 0, B: 1, C: 2),
+    (A: 0, B: 1, C: 2, D: 3),
+    (A: 0, B: 1, C: 2, D: 3, E: 4),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7),
+);
+
+/// Reason why a port lock sequence failed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockError {
+    /// The port has already been locked since the last reset
+    AlreadyLocked,
+    /// LCKK did not read back as set after the lock sequence
+    NotLatched,
+}
+
+/// The 16 pins of a port, each of which starts as a floating input
+///
+/// PA13, PA14, PA15, PB3 and PB4 are actually debug port pins after reset
+/// and only become what their type says once they are reconfigured.
+pub struct Parts<PORT> {
+    pub port: Port<PORT>,
+    pub p0: Pin<PORT, P0, Input<Floating>>,
+    pub p1: Pin<PORT, P1, Input<Floating>>,
+    pub p2: Pin<PORT, P2, Input<Floating>>,
+    pub p3: Pin<PORT, P3, Input<Floating>>,
+    pub p4: Pin<PORT, P4, Input<Floating>>,
+    pub p5: Pin<PORT, P5, Input<Floating>>,
+    pub p6: Pin<PORT, P6, Input<Floating>>,
+    pub p7: Pin<PORT, P7, Input<Floating>>,
+    pub p8: Pin<PORT, P8, Input<Floating>>,
+    pub p9: Pin<PORT, P9, Input<Floating>>,
+    pub p10: Pin<PORT, P10, Input<Floating>>,
+    pub p11: Pin<PORT, P11, Input<Floating>>,
+    pub p12: Pin<PORT, P12, Input<Floating>>,
+    pub p13: Pin<PORT, P13, Input<Floating>>,
+    pub p14: Pin<PORT, P14, Input<Floating>>,
+    pub p15: Pin<PORT, P15, Input<Floating>>,
+}
+
+macro_rules! ports {
+    ($($PORT:ident: [$($PXi:ident: $Pi:ident,)+],)+) => {
+        $(
+            impl $PORT {
+                /// Enables the port clock and splits the port into individually owned pins
+                pub fn split(self, rcc: &rcc::RCC) -> Parts<$PORT> {
+                    <$PORT as rcc::Enable>::enable(rcc);
+                    Parts {
+                        port: Port { _port: PhantomData },
+                        p0: Pin::new(),
+                        p1: Pin::new(),
+                        p2: Pin::new(),
+                        p3: Pin::new(),
+                        p4: Pin::new(),
+                        p5: Pin::new(),
+                        p6: Pin::new(),
+                        p7: Pin::new(),
+                        p8: Pin::new(),
+                        p9: Pin::new(),
+                        p10: Pin::new(),
+                        p11: Pin::new(),
+                        p12: Pin::new(),
+                        p13: Pin::new(),
+                        p14: Pin::new(),
+                        p15: Pin::new(),
+                    }
+                }
+            }
+
+            $(
+                pub type $PXi<MODE> = Pin<$PORT, $Pi, MODE>;
+            )+
+        )+
+    }
+}
+
+ports!(
+    GPIOA: [PA0: P0, PA1: P1, PA2: P2, PA3: P3, PA4: P4, PA5: P5, PA6: P6, PA7: P7,
+            PA8: P8, PA9: P9, PA10: P10, PA11: P11, PA12: P12, PA13: P13, PA14: P14, PA15: P15,],
+    GPIOB: [PB0: P0, PB1: P1, PB2: P2, PB3: P3, PB4: P4, PB5: P5, PB6: P6, PB7: P7,
+            PB8: P8, PB9: P9, PB10: P10, PB11: P11, PB12: P12, PB13: P13, PB14: P14, PB15: P15,],
+    GPIOC: [PC0: P0, PC1: P1, PC2: P2, PC3: P3, PC4: P4, PC5: P5, PC6: P6, PC7: P7,
+            PC8: P8, PC9: P9, PC10: P10, PC11: P11, PC12: P12, PC13: P13, PC14: P14, PC15: P15,],
+    GPIOD: [PD0: P0, PD1: P1, PD2: P2, PD3: P3, PD4: P4, PD5: P5, PD6: P6, PD7: P7,
+            PD8: P8, PD9: P9, PD10: P10, PD11: P11, PD12: P12, PD13: P13, PD14: P14, PD15: P15,],
+    GPIOE: [PE0: P0, PE1: P1, PE2: P2, PE3: P3, PE4: P4, PE5: P5, PE6: P6, PE7: P7,
+            PE8: P8, PE9: P9, PE10: P10, PE11: P11, PE12: P12, PE13: P13, PE14: P14, PE15: P15,],
+    GPIOH: [PH0: P0, PH1: P1, PH2: P2, PH3: P3, PH4: P4, PH5: P5, PH6: P6, PH7: P7,
+            PH8: P8, PH9: P9, PH10: P10, PH11: P11, PH12: P12, PH13: P13, PH14: P14, PH15: P15,],
+);
+
+macro_rules! pin_indices {
+    ($($Pi:ident: $i:expr,)+) => {
+        $(
+            /// Pin position within a port (type state)
+            pub struct $Pi;
+
+            impl PinIndex for $Pi {
+                const INDEX: u32 = $i;
+            }
+        )+
+    }
+}
+
+pin_indices!(
+    P0: 0, P1: 1, P2: 2, P3: 3, P4: 4, P5: 5, P6: 6, P7: 7,
+    P8: 8, P9: 9, P10: 10, P11: 11, P12: 12, P13: 13, P14: 14, P15: 15,
+);
+
+/// Input mode (type state)
+pub struct Input<PULL> {
+    _pull: PhantomData<PULL>,
+}
+
+/// Floating input (type state)
+pub struct Floating;
+/// Pulled up input (type state)
+pub struct PullUp;
+/// Pulled down input (type state)
+pub struct PullDown;
+
+/// Output mode (type state)
+pub struct Output<OTYPE> {
+    _otype: PhantomData<OTYPE>,
+}
+
+/// Push pull output (type state)
+pub struct PushPull;
+/// Open drain output (type state)
+pub struct OpenDrain;
+
+/// Alternate function mode (type state)
+pub struct Alternate<AF> {
+    _af: PhantomData<AF>,
+}
+
+/// Analog mode (type state)
+pub struct Analog;
+
+/// Internal resistor setting of an input type state
+pub trait Pull {
+    const PUPDR: pupdr::Pupdr;
+}
+
+impl Pull for Floating {
+    const PUPDR: pupdr::Pupdr = pupdr::Pupdr::NoPuPd;
+}
+
+impl Pull for PullUp {
+    const PUPDR: pupdr::Pupdr = pupdr::Pupdr::PullUp;
+}
+
+impl Pull for PullDown {
+    const PUPDR: pupdr::Pupdr = pupdr::Pupdr::PuuDown;
+}
+
+/// Output driver setting of an output type state
+pub trait OutputType {
+    const OTY: otyper::Oty;
+}
+
+impl OutputType for PushPull {
+    const OTY: otyper::Oty = otyper::Oty::PushPull;
+}
+
+impl OutputType for OpenDrain {
+    const OTY: otyper::Oty = otyper::Oty::OpenDrain;
+}
+
+/// Alternate function number of an alternate type state
+pub trait AlternateFunction {
+    const AFRY: afr::Afry;
+}
+
+macro_rules! alternate_functions {
+    ($($AF:ident,)+) => {
+        $(
+            /// Alternate function number (type state)
+            pub struct $AF;
+
+            impl AlternateFunction for $AF {
+                const AFRY: afr::Afry = afr::Afry::$AF;
+            }
+        )+
+    }
+}
+
+alternate_functions!(
+    AF0, AF1, AF2, AF3, AF4, AF5, AF6, AF7, AF8, AF9, AF10, AF11, AF12, AF13, AF14, AF15,
+);
+
+/// Runtime equivalent of the pin type states
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Input(pupdr::Pupdr),
+    Output(otyper::Oty),
+    Alternate(afr::Afry),
+    Analog,
+}
+
+/// A pin type state with a runtime `Mode` equivalent
+pub trait PinMode {
+    const MODE: Mode;
+}
+
+impl<PULL: Pull> PinMode for Input<PULL> {
+    const MODE: Mode = Mode::Input(PULL::PUPDR);
+}
+
+impl<OTYPE: OutputType> PinMode for Output<OTYPE> {
+    const MODE: Mode = Mode::Output(OTYPE::OTY);
+}
+
+impl<AF: AlternateFunction> PinMode for Alternate<AF> {
+    const MODE: Mode = Mode::Alternate(AF::AFRY);
+}
+
+impl PinMode for Analog {
+    const MODE: Mode = Mode::Analog;
+}
+
+/// Pin `N` of port `PORT` in mode `MODE`
+pub struct Pin<PORT, N, MODE> {
+    _port: PhantomData<PORT>,
+    _index: PhantomData<N>,
+    _mode: PhantomData<MODE>,
+}
+
+impl<PORT: PortId, N: PinIndex, MODE> Pin<PORT, N, MODE> {
+    fn new() -> Self {
+        Pin {
+            _port: PhantomData,
+            _index: PhantomData,
+            _mode: PhantomData,
+        }
+    }
+
+    /// Returns the pin number within its port
+    pub fn index(&self) -> u32 {
+        N::INDEX
+    }
+
+    /// Configures the pin to operate as an input with the `PULL` resistor setting
+    pub fn into_input<PULL: Pull>(self) -> Pin<PORT, N, Input<PULL>> {
+        self.into_mode()
+    }
+
+    /// Configures the pin to operate as a floating input
+    pub fn into_floating_input(self) -> Pin<PORT, N, Input<Floating>> {
+        self.into_input()
+    }
+
+    /// Configures the pin to operate as a pulled up input
+    pub fn into_pull_up_input(self) -> Pin<PORT, N, Input<PullUp>> {
+        self.into_input()
+    }
+
+    /// Configures the pin to operate as a pulled down input
+    pub fn into_pull_down_input(self) -> Pin<PORT, N, Input<PullDown>> {
+        self.into_input()
+    }
+
+    /// Configures the pin to operate as a general purpose output with the `OTYPE` driver
+    pub fn into_output<OTYPE: OutputType>(self) -> Pin<PORT, N, Output<OTYPE>> {
+        self.into_mode()
+    }
+
+    /// Configures the pin to operate as a push pull output
+    pub fn into_push_pull_output(self) -> Pin<PORT, N, Output<PushPull>> {
+        self.into_output()
+    }
+
+    /// Configures the pin to operate as an open drain output
+    pub fn into_open_drain_output(self) -> Pin<PORT, N, Output<OpenDrain>> {
+        self.into_output()
+    }
+
+    /// Configures the pin to operate as alternate function `AF`
+    ///
+    /// The pin is left as a push pull output with no pull resistor.
+    pub fn into_alternate<AF: AlternateFunction>(self) -> Pin<PORT, N, Alternate<AF>> {
+        self.into_mode()
+    }
+
+    /// Configures the pin to operate as an analog pin
+    pub fn into_analog(self) -> Pin<PORT, N, Analog> {
+        self.into_mode()
+    }
+
+    fn regs(&self) -> &RegisterMap {
+        unsafe { &*(PORT::BASE as *const RegisterMap) }
+    }
+
+    pub(crate) fn into_mode<NEW: PinMode>(self) -> Pin<PORT, N, NEW> {
+        configure(self.regs(), N::INDEX, NEW::MODE);
+        Pin::new()
+    }
+
+    fn write_otyper(&self, otype: otyper::Oty) {
+        unsafe {
+            write_field(&self.regs().otyper, N::INDEX, 0b1, otype as u32);
+        }
+    }
+
+    fn write_ospeedr(&self, speed: ospeedr::Ospeedr) {
+        unsafe {
+            write_field(&self.regs().ospeedr, N::INDEX * 2, 0b11, speed as u32);
+        }
+    }
+
+    fn write_pupdr(&self, pupd: pupdr::Pupdr) {
+        unsafe {
+            write_field(&self.regs().pupdr, N::INDEX * 2, 0b11, pupd as u32);
+        }
+    }
+
+    fn read_idr(&self) -> bool {
+        self.regs().idr.read() & (1 << N::INDEX) != 0
+    }
+
+    fn read_odr(&self) -> bool {
+        self.regs().odr.read() & (1 << N::INDEX) != 0
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, PULL> Pin<PORT, N, Input<PULL>> {
+    /// Returns true if the input level of the pin is high
+    pub fn is_high(&self) -> bool {
+        self.read_idr()
+    }
+
+    /// Returns true if the input level of the pin is low
+    pub fn is_low(&self) -> bool {
+        !self.read_idr()
+    }
+
+    /// Routes the pin to EXTI line `N` and returns a handle to that line
+    ///
+    /// Each EXTI line is shared by the pins with the same number on every port,
+    /// so this replaces whichever port was routed to the line before.
+    pub fn make_interrupt_source(&mut self, syscfg: &mut syscfg::Syscfg) -> exti::Line {
+        syscfg.set_exti_source(N::INDEX as u8, PORT::EXTI_SOURCE);
+        exti::Line::new(N::INDEX as u8)
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> Pin<PORT, N, Output<OTYPE>> {
+    /// Selects the output speed
+    pub fn set_speed(&mut self, speed: ospeedr::Ospeedr) {
+        self.write_ospeedr(speed);
+    }
+
+    /// Drives the pin high
+    pub fn set_high(&mut self) {
+        // BSRR stores are atomic, so other pins of the port are never disturbed
+        unsafe { self.regs().bsrr.write(1 << N::INDEX) }
+    }
+
+    /// Drives the pin low
+    pub fn set_low(&mut self) {
+        unsafe { self.regs().bsrr.write(1 << (N::INDEX + 16)) }
+    }
+
+    /// Inverts the output level of the pin
+    pub fn toggle(&mut self) {
+        if self.read_odr() {
+            self.set_low();
+        } else {
+            self.set_high();
+        }
+    }
+
+    /// Returns true if the pin is driven high
+    pub fn is_set_high(&self) -> bool {
+        self.read_odr()
+    }
+
+    /// Returns true if the pin is driven low
+    pub fn is_set_low(&self) -> bool {
+        !self.read_odr()
+    }
+
+    /// Returns true if the input level of the pin is high
+    pub fn is_high(&self) -> bool {
+        self.read_idr()
+    }
+
+    /// Returns true if the input level of the pin is low
+    pub fn is_low(&self) -> bool {
+        !self.read_idr()
+    }
+}
+
+impl<PORT: PortId, N: PinIndex> Pin<PORT, N, Output<OpenDrain>> {
+    /// Selects the internal pull-up/pull-down resistor
+    pub fn set_pull(&mut self, pupd: pupdr::Pupdr) {
+        self.write_pupdr(pupd);
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, AF> Pin<PORT, N, Alternate<AF>> {
+    /// Selects the output speed
+    pub fn set_speed(&mut self, speed: ospeedr::Ospeedr) {
+        self.write_ospeedr(speed);
+    }
+
+    /// Selects push pull or open drain output
+    pub fn set_output_type(&mut self, otype: otyper::Oty) {
+        self.write_otyper(otype);
+    }
+
+    /// Selects the internal pull-up/pull-down resistor
+    pub fn set_pull(&mut self, pupd: pupdr::Pupdr) {
+        self.write_pupdr(pupd);
+    }
+}
+
+/// A pin whose configuration is frozen by the port lock until the next reset
+pub struct LockedPin<PORT, N, MODE> {
+    pin: Pin<PORT, N, MODE>,
+}
+
+impl<PORT: PortId, N: PinIndex, MODE> LockedPin<PORT, N, MODE> {
+    /// Returns the pin number within its port
+    pub fn index(&self) -> u32 {
+        N::INDEX
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, PULL> LockedPin<PORT, N, Input<PULL>> {
+    /// Returns true if the input level of the pin is high
+    pub fn is_high(&self) -> bool {
+        self.pin.is_high()
+    }
+
+    /// Returns true if the input level of the pin is low
+    pub fn is_low(&self) -> bool {
+        self.pin.is_low()
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> LockedPin<PORT, N, Output<OTYPE>> {
+    /// Drives the pin high
+    pub fn set_high(&mut self) {
+        self.pin.set_high();
+    }
+
+    /// Drives the pin low
+    pub fn set_low(&mut self) {
+        self.pin.set_low();
+    }
+
+    /// Inverts the output level of the pin
+    pub fn toggle(&mut self) {
+        self.pin.toggle();
+    }
+
+    /// Returns true if the pin is driven high
+    pub fn is_set_high(&self) -> bool {
+        self.pin.is_set_high()
+    }
+
+    /// Returns true if the pin is driven low
+    pub fn is_set_low(&self) -> bool {
+        self.pin.is_set_low()
+    }
+
+    /// Returns true if the input level of the pin is high
+    pub fn is_high(&self) -> bool {
+        self.pin.is_high()
+    }
+
+    /// Returns true if the input level of the pin is low
+    pub fn is_low(&self) -> bool {
+        self.pin.is_low()
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, PULL> InputPin for Pin<PORT, N, Input<PULL>> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(self.read_idr())
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(!self.read_idr())
+    }
+}
+
+impl<PORT: PortId, N: PinIndex> InputPin for Pin<PORT, N, Output<OpenDrain>> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(self.read_idr())
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(!self.read_idr())
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> OutputPin for Pin<PORT, N, Output<OTYPE>> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Pin::set_low(self);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Pin::set_high(self);
+        Ok(())
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> StatefulOutputPin for Pin<PORT, N, Output<OTYPE>> {
+    fn is_set_high(&self) -> Result<bool, Infallible> {
+        Ok(self.read_odr())
+    }
+
+    fn is_set_low(&self) -> Result<bool, Infallible> {
+        Ok(!self.read_odr())
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> ToggleableOutputPin for Pin<PORT, N, Output<OTYPE>> {
+    type Error = Infallible;
+
+    fn toggle(&mut self) -> Result<(), Infallible> {
+        Pin::toggle(self);
+        Ok(())
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, PULL> InputPin for LockedPin<PORT, N, Input<PULL>> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        InputPin::is_high(&self.pin)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        InputPin::is_low(&self.pin)
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> OutputPin for LockedPin<PORT, N, Output<OTYPE>> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        OutputPin::set_low(&mut self.pin)
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        OutputPin::set_high(&mut self.pin)
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> StatefulOutputPin for LockedPin<PORT, N, Output<OTYPE>> {
+    fn is_set_high(&self) -> Result<bool, Infallible> {
+        StatefulOutputPin::is_set_high(&self.pin)
+    }
+
+    fn is_set_low(&self) -> Result<bool, Infallible> {
+        StatefulOutputPin::is_set_low(&self.pin)
+    }
+}
+
+impl<PORT: PortId, N: PinIndex, OTYPE> ToggleableOutputPin for LockedPin<PORT, N, Output<OTYPE>> {
+    type Error = Infallible;
+
+    fn toggle(&mut self) -> Result<(), Infallible> {
+        ToggleableOutputPin::toggle(&mut self.pin)
+    }
+}
+
+/// A pin whose port, position and mode are only known at runtime
+///
+/// Unlike `Pin`, every operation that depends on the mode is checked when it is called.
+pub struct AnyPin {
+    port: PortName,
+    index: u32,
+    mode: Mode,
+}
+
+/// Reason why an `AnyPin` operation was refused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinError {
+    /// The pin configuration is frozen by the port lock
+    Locked,
+    /// The operation is not available in the current mode of the pin
+    WrongMode,
+}
+
+impl<PORT: PortId, N: PinIndex, MODE: PinMode> From<Pin<PORT, N, MODE>> for AnyPin {
+    fn from(_pin: Pin<PORT, N, MODE>) -> AnyPin {
+        AnyPin {
+            port: PORT::NAME,
+            index: N::INDEX,
+            mode: MODE::MODE,
+        }
+    }
+}
+
+impl AnyPin {
+    /// Returns the port of the pin
+    pub fn port(&self) -> PortName {
+        self.port
+    }
+
+    /// Returns the pin number within its port
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the current mode of the pin
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Reconfigures the pin into `mode`
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), PinError> {
+        self.check_unlocked()?;
+        configure(self.regs(), self.index, mode);
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Selects the output speed of an output or alternate function pin
+    pub fn set_speed(&mut self, speed: ospeedr::Ospeedr) -> Result<(), PinError> {
+        self.check_unlocked()?;
+        match self.mode {
+            Mode::Output(_) | Mode::Alternate(_) => unsafe {
+                write_field(&self.regs().ospeedr, self.index * 2, 0b11, speed as u32);
+                Ok(())
+            },
+            _ => Err(PinError::WrongMode),
+        }
+    }
+
+    /// Drives an output pin high
+    pub fn set_high(&mut self) -> Result<(), PinError> {
+        self.check_output()?;
+        unsafe { self.regs().bsrr.write(1 << self.index) }
+        Ok(())
+    }
+
+    /// Drives an output pin low
+    pub fn set_low(&mut self) -> Result<(), PinError> {
+        self.check_output()?;
+        unsafe { self.regs().bsrr.write(1 << (self.index + 16)) }
+        Ok(())
+    }
+
+    /// Inverts the output level of an output pin
+    pub fn toggle(&mut self) -> Result<(), PinError> {
+        if self.is_set_high()? {
+            self.set_low()
+        } else {
+            self.set_high()
+        }
+    }
+
+    /// Returns true if an output pin is driven high
+    pub fn is_set_high(&self) -> Result<bool, PinError> {
+        self.check_output()?;
+        Ok(self.regs().odr.read() & (1 << self.index) != 0)
+    }
+
+    /// Returns true if the input level of the pin is high
+    ///
+    /// The input buffer is disabled in analog mode, so analog pins are refused.
+    pub fn is_high(&self) -> Result<bool, PinError> {
+        match self.mode {
+            Mode::Analog => Err(PinError::WrongMode),
+            _ => Ok(self.regs().idr.read() & (1 << self.index) != 0),
+        }
+    }
+
+    fn regs(&self) -> &RegisterMap {
+        unsafe { &*(self.port.base() as *const RegisterMap) }
+    }
+
+    fn check_output(&self) -> Result<(), PinError> {
+        match self.mode {
+            Mode::Output(_) => Ok(()),
+            _ => Err(PinError::WrongMode),
+        }
+    }
+
+    fn check_unlocked(&self) -> Result<(), PinError> {
+        let bits = self.regs().lckr.read();
+        if bits & lckr::LCKK != 0 && bits & (1 << self.index) != 0 {
+            Err(PinError::Locked)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Full configuration of one pin, as an entry of a `PortConfig` table
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PinConfig {
+    pub index: u32,
+    pub mode: moder::Modery,
+    pub otype: otyper::Oty,
+    pub speed: ospeedr::Ospeedr,
+    pub pupd: pupdr::Pupdr,
+    pub af: afr::Afry,
+}
+
+/// Configuration register values of a whole port, for the pins in `mask`
+///
+/// The register values are computed up front, so applying the configuration costs
+/// a single read-modify-write per register no matter how many pins it covers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PortConfig {
+    mask: u16,
+    moder: u32,
+    otyper: u32,
+    ospeedr: u32,
+    pupdr: u32,
+    afr: [u32; 2],
+}
+
+impl PortConfig {
+    /// Creates a configuration that covers no pin
+    pub fn new() -> PortConfig {
+        PortConfig {
+            mask: 0,
+            moder: 0,
+            otyper: 0,
+            ospeedr: 0,
+            pupdr: 0,
+            afr: [0; 2],
+        }
+    }
+
+    /// Creates a configuration from a table of pin configurations
+    pub fn from_table(table: &[PinConfig]) -> PortConfig {
+        table.iter().fold(PortConfig::new(), |config, pin| config.pin(*pin))
+    }
+
+    /// Reads back the live configuration of the pins in `mask`, through the `Port`
+    /// returned by `apply` or by `split`
+    pub fn read<PORT: PortId>(port: &Port<PORT>, mask: u16) -> PortConfig {
+        let regs = port.regs();
+        let masks = Masks::new(mask);
+        PortConfig {
+            mask,
+            moder: regs.moder.read() & masks.x2,
+            otyper: regs.otyper.read() & masks.x1,
+            ospeedr: regs.ospeedr.read() & masks.x2,
+            pupdr: regs.pupdr.read() & masks.x2,
+            afr: [regs.afr[0].read() & masks.x4[0], regs.afr[1].read() & masks.x4[1]],
+        }
+    }
+
+    /// Adds or replaces the configuration of one pin
+    ///
+    /// Panics if `pin.index` is not a pin number between 0 and 15.
+    pub fn pin(mut self, pin: PinConfig) -> PortConfig {
+        assert!(pin.index < 16);
+        let i = pin.index;
+        let (reg, offset) = if i < 8 { (0, i * 4) } else { (1, (i - 8) * 4) };
+
+        self.mask |= 1 << i;
+        self.moder = (self.moder & !(0b11 << (i * 2))) | (pin.mode as u32) << (i * 2);
+        self.otyper = (self.otyper & !(0b1 << i)) | (pin.otype as u32) << i;
+        self.ospeedr = (self.ospeedr & !(0b11 << (i * 2))) | (pin.speed as u32) << (i * 2);
+        self.pupdr = (self.pupdr & !(0b11 << (i * 2))) | (pin.pupd as u32) << (i * 2);
+        self.afr[reg] = (self.afr[reg] & !(0b1111 << offset)) | (pin.af as u32) << offset;
+        self
+    }
+
+    /// Returns the pins covered by the configuration
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    /// Enables the port clock and writes the configuration to the port, leaving the
+    /// other pins untouched
+    ///
+    /// The unsplit port is consumed, so the table and typed `Pin`s can never both
+    /// control the same port. Port wide access is handed back as a `Port`.
+    pub fn apply<PORT: PortId + rcc::Enable>(&self, _port: PORT, rcc: &rcc::RCC) -> Port<PORT> {
+        <PORT as rcc::Enable>::enable(rcc);
+        let port = Port { _port: PhantomData };
+        let regs = port.regs();
+        let masks = Masks::new(self.mask);
+        unsafe {
+            regs.afr[0].modify(|v| (v & !masks.x4[0]) | self.afr[0]);
+            regs.afr[1].modify(|v| (v & !masks.x4[1]) | self.afr[1]);
+            regs.otyper.modify(|v| (v & !masks.x1) | self.otyper);
+            regs.ospeedr.modify(|v| (v & !masks.x2) | self.ospeedr);
+            regs.pupdr.modify(|v| (v & !masks.x2) | self.pupdr);
+            regs.moder.modify(|v| (v & !masks.x2) | self.moder);
+        }
+        port
+    }
+
+    /// Returns the pins whose live configuration differs from this configuration
+    pub fn diff<PORT: PortId>(&self, port: &Port<PORT>) -> u16 {
+        let live = PortConfig::read(port, self.mask);
+        (0..16).filter(|&i| self.mask & (1 << i) != 0).fold(0, |diff, i| {
+            let masks = Masks::new(1 << i);
+            let differs = (live.moder ^ self.moder) & masks.x2 != 0 ||
+                (live.otyper ^ self.otyper) & masks.x1 != 0 ||
+                (live.ospeedr ^ self.ospeedr) & masks.x2 != 0 ||
+                (live.pupdr ^ self.pupdr) & masks.x2 != 0 ||
+                (live.afr[0] ^ self.afr[0]) & masks.x4[0] != 0 ||
+                (live.afr[1] ^ self.afr[1]) & masks.x4[1] != 0;
+            if differs { diff | 1 << i } else { diff }
+        })
+    }
+}
+
+/// Register masks of a set of pins, for 1, 2 and 4 bit wide fields
+struct Masks {
+    x1: u32,
+    x2: u32,
+    x4: [u32; 2],
+}
+
+impl Masks {
+    fn new(pins: u16) -> Masks {
+        let mut masks = Masks {
+            x1: pins as u32,
+            x2: 0,
+            x4: [0; 2],
+        };
+        for i in 0..16 {
+            if pins & (1 << i) != 0 {
+                masks.x2 |= 0b11 << (i * 2);
+                masks.x4[i / 8] |= 0b1111 << ((i % 8) * 4);
+            }
+        }
+        masks
+    }
+}
+
+/// Writes every configuration register of pin `index` for `mode`
+///
+/// The mode register is written last, so the pin never briefly drives with a stale setting.
+fn configure(regs: &RegisterMap, index: u32, mode: Mode) {
+    let (pupd, modery) = match mode {
+        Mode::Input(pupd) => (pupd, moder::Modery::Input),
+        Mode::Output(_) => (pupdr::Pupdr::NoPuPd, moder::Modery::Output),
+        Mode::Alternate(_) => (pupdr::Pupdr::NoPuPd, moder::Modery::Alternate),
+        Mode::Analog => (pupdr::Pupdr::NoPuPd, moder::Modery::Analog),
+    };
+    unsafe {
+        match mode {
+            Mode::Output(otype) => {
+                write_field(&regs.otyper, index, 0b1, otype as u32);
+            }
+            Mode::Alternate(af) => {
+                let (reg, offset) = if index < 8 {
+                    (0, index * 4)
+                } else {
+                    (1, (index - 8) * 4)
+                };
+                write_field(&regs.afr[reg], offset, 0b1111, af as u32);
+                write_field(&regs.otyper, index, 0b1, otyper::Oty::PushPull as u32);
+            }
+            _ => {}
+        }
+        write_field(&regs.pupdr, index * 2, 0b11, pupd as u32);
+        write_field(&regs.moder, index * 2, 0b11, modery as u32);
+    }
+}
+
+/// Replaces the `mask` wide field at bit `offset` of `reg` with `value`
+unsafe fn write_field(reg: &RW<u32>, offset: u32, mask: u32, value: u32) {
+    reg.modify(|v| (v & !(mask << offset)) | ((value & mask) << offset));
+}
+
 pub mod moder {
     /// Port x configuration bits
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Modery {
         Input = 0b00,
         Output = 0b01,
@@ -33,7 +1133,7 @@ pub mod moder {
 
 pub mod otyper {
     /// Port x configuration bits
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Oty {
         PushPull = 0b0,
         OpenDrain = 0b1,
@@ -42,7 +1142,7 @@ pub mod otyper {
 
 pub mod ospeedr {
     /// Port x configuration bits
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Ospeedr {
         Low = 0b00,
         Medium = 0b01,
@@ -53,7 +1153,7 @@ pub mod ospeedr {
 
 pub mod pupdr {
     /// Port x configuration bits
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Pupdr {
         NoPuPd = 0b00,
         PullUp = 0b01,
@@ -61,9 +1161,14 @@ pub mod pupdr {
     }
 }
 
+pub mod lckr {
+    /// Lock key
+    pub const LCKK: u32 = 0b1 << 16;
+}
+
 pub mod afr {
     /// Alternate function selection for port x bit (0..7)
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Afry {
         AF0 = 0b0000,
         AF1 = 0b0001,
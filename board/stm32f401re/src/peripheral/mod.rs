@@ -1,3 +1,52 @@
+use cortex_m;
+
+/// Defines an owned handle to the register block at `$BASE`
+///
+/// The handle dereferences to the `RegisterMap` of the module it is defined in.
+macro_rules! peripheral {
+    ($(#[$attr:meta])* $NAME:ident, $BASE:expr) => {
+        $(#[$attr])*
+        pub struct $NAME {
+            _marker: ::core::marker::PhantomData<*const ()>,
+        }
+
+        unsafe impl Send for $NAME {}
+
+        impl $NAME {
+            /// Returns a pointer to the register block
+            pub fn ptr() -> *const RegisterMap {
+                $BASE as *const RegisterMap
+            }
+
+            pub(crate) unsafe fn new() -> $NAME {
+                $NAME { _marker: ::core::marker::PhantomData }
+            }
+        }
+
+        impl ::core::ops::Deref for $NAME {
+            type Target = RegisterMap;
+
+            fn deref(&self) -> &RegisterMap {
+                unsafe { &*$NAME::ptr() }
+            }
+        }
+    }
+}
+
+/// Defines zero-sized markers for peripherals that have no register map in this crate
+///
+/// The markers name the peripheral in pin signal traits and in the `rcc` clock traits.
+macro_rules! instances {
+    ($($NAME:ident,)+) => {
+        $(
+            /// Peripheral instance (type state)
+            #[allow(non_camel_case_types)]
+            pub struct $NAME;
+        )+
+    }
+}
+
+pub mod af;
 pub mod exti;
 pub mod flash;
 pub mod gpio;
@@ -5,3 +54,65 @@ pub mod pwr;
 pub mod rcc;
 pub mod spi;
 pub mod syscfg;
+
+instances!(
+    ADC1, CRC, DMA1, DMA2, I2C1, I2C2, I2C3, OTG_FS, SDIO, TIM1, TIM2, TIM3, TIM4, TIM5, TIM9,
+    TIM10, TIM11, USART1, USART2, USART6, WWDG,
+);
+
+static mut TAKEN: bool = false;
+
+/// All device peripherals, each of which can only be owned once
+#[allow(non_snake_case)]
+pub struct Peripherals {
+    pub EXTI: exti::EXTI,
+    pub FLASH: flash::FLASH,
+    pub GPIOA: gpio::GPIOA,
+    pub GPIOB: gpio::GPIOB,
+    pub GPIOC: gpio::GPIOC,
+    pub GPIOD: gpio::GPIOD,
+    pub GPIOE: gpio::GPIOE,
+    pub GPIOH: gpio::GPIOH,
+    pub PWR: pwr::PWR,
+    pub RCC: rcc::RCC,
+    pub SPI1: spi::SPI1,
+    pub SPI2: spi::SPI2,
+    pub SPI3: spi::SPI3,
+    pub SPI4: spi::SPI4,
+    pub SYSCFG: syscfg::SYSCFG,
+}
+
+impl Peripherals {
+    /// Returns all the peripherals the first time it is called, and `None` afterwards
+    pub fn take() -> Option<Peripherals> {
+        cortex_m::interrupt::free(|_| if unsafe { TAKEN } {
+            None
+        } else {
+            Some(unsafe { Peripherals::steal() })
+        })
+    }
+
+    /// Returns all the peripherals, even if they have already been taken
+    ///
+    /// This is unsafe because it can create aliases of peripherals that are already owned.
+    pub unsafe fn steal() -> Peripherals {
+        TAKEN = true;
+        Peripherals {
+            EXTI: exti::EXTI::new(),
+            FLASH: flash::FLASH::new(),
+            GPIOA: gpio::GPIOA::new(),
+            GPIOB: gpio::GPIOB::new(),
+            GPIOC: gpio::GPIOC::new(),
+            GPIOD: gpio::GPIOD::new(),
+            GPIOE: gpio::GPIOE::new(),
+            GPIOH: gpio::GPIOH::new(),
+            PWR: pwr::PWR::new(),
+            RCC: rcc::RCC::new(),
+            SPI1: spi::SPI1::new(),
+            SPI2: spi::SPI2::new(),
+            SPI3: spi::SPI3::new(),
+            SPI4: spi::SPI4::new(),
+            SYSCFG: syscfg::SYSCFG::new(),
+        }
+    }
+}
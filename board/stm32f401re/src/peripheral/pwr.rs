@@ -8,6 +8,11 @@ pub struct RegisterMap {
     pub csr: RW<u32>,
 }
 
+peripheral!(
+    /// Power controller
+    PWR, PWR_BASE
+);
+
 pub mod cr {
     /// Regulator voltage scalling output selection
     pub const VOS_MASK: u32 = 0b11 << 14;
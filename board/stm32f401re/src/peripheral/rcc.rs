@@ -1,5 +1,13 @@
 #![allow(dead_code)]
+use core::cmp;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use cortex_m::asm;
 use volatile_register::RW;
+use peripheral::{af, flash, gpio, pwr, spi, syscfg};
+use peripheral::{ADC1, CRC, DMA1, DMA2, OTG_FS, SDIO, TIM1, TIM2, TIM3, TIM4, TIM5, TIM9, TIM10};
+use peripheral::{I2C1, I2C2, I2C3, TIM11, USART1, USART2, USART6, WWDG};
 
 pub const RCC_BASE: u32 = 0x4002_3800;
 
@@ -36,6 +44,1657 @@ pub struct RegisterMap {
     pub dckcfgr: RW<u32>,
 }
 
+peripheral!(
+    /// Reset and clock control
+    RCC, RCC_BASE
+);
+
+/// Peripheral whose bus clock is gated in RCC
+pub trait Enable {
+    /// Enables the bus clock of the peripheral
+    fn enable(rcc: &RCC);
+    /// Disables the bus clock of the peripheral
+    fn disable(rcc: &RCC);
+    /// Returns true if the bus clock of the peripheral is enabled
+    fn is_enabled(rcc: &RCC) -> bool;
+    /// Keeps the bus clock running while the core is in Sleep mode
+    fn enable_in_sleep(rcc: &RCC);
+    /// Stops the bus clock while the core is in Sleep mode
+    fn disable_in_sleep(rcc: &RCC);
+    /// Returns true if the bus clock keeps running in Sleep mode
+    fn is_enabled_in_sleep(rcc: &RCC) -> bool;
+}
+
+/// Peripheral that can be reset through RCC
+pub trait Reset {
+    /// Pulses the reset line, returning every register of the peripheral to its reset value
+    fn reset(rcc: &RCC);
+}
+
+macro_rules! bus {
+    ($enr:ident, $rstr:ident, $lpenr:ident: [
+        $($PER:ty: ($en:ident, $rst:ident, $lpen:ident),)+
+    ]) => {
+        $(
+            impl Enable for $PER {
+                fn enable(rcc: &RCC) {
+                    unsafe { rcc.$enr.modify(|v| v | $enr::$en::Enable as u32) }
+                    // Dummy read so the clock is running before the first register access
+                    let _ = rcc.$enr.read();
+                }
+
+                fn disable(rcc: &RCC) {
+                    unsafe { rcc.$enr.modify(|v| v & !($enr::$en::Enable as u32)) }
+                }
+
+                fn is_enabled(rcc: &RCC) -> bool {
+                    rcc.$enr.read() & $enr::$en::Enable as u32 != 0
+                }
+
+                fn enable_in_sleep(rcc: &RCC) {
+                    unsafe { rcc.$lpenr.modify(|v| v | $lpenr::$lpen::Enable as u32) }
+                }
+
+                fn disable_in_sleep(rcc: &RCC) {
+                    unsafe { rcc.$lpenr.modify(|v| v & !($lpenr::$lpen::Enable as u32)) }
+                }
+
+                fn is_enabled_in_sleep(rcc: &RCC) -> bool {
+                    rcc.$lpenr.read() & $lpenr::$lpen::Enable as u32 != 0
+                }
+            }
+
+            impl Reset for $PER {
+                fn reset(rcc: &RCC) {
+                    unsafe {
+                        rcc.$rstr.modify(|v| v | $rstr::$rst::Reset as u32);
+                        rcc.$rstr.modify(|v| v & !($rstr::$rst::Reset as u32));
+                    }
+                }
+            }
+        )+
+    }
+}
+
+bus!(ahb1enr, ahb1rstr, ahb1lpenr: [
+    gpio::GPIOA: (Gpioaen, Gpioarst, Gpioalpen),
+    gpio::GPIOB: (Gpioben, Gpiobrst, Gpioblpen),
+    gpio::GPIOC: (Gpiocen, Gpiocrst, Gpioclpen),
+    gpio::GPIOD: (Gpioden, Gpiodrst, Gpiodlpen),
+    gpio::GPIOE: (Gpioeen, Gpioerst, Gpioelpen),
+    gpio::GPIOH: (Gpiohen, Gpiohrst, Gpiohlpen),
+    CRC: (Crcen, Crcrst, Crclpen),
+    DMA1: (Dma1en, Dma1rst, Dma1lpen),
+    DMA2: (Dma2en, Dma2rst, Dma2lpen),
+]);
+
+bus!(ahb2enr, ahb2rstr, ahb2lpenr: [
+    OTG_FS: (Otgfsen, Otgfsrst, Otgfslpen),
+]);
+
+bus!(apb1enr, apb1rstr, apb1lpenr: [
+    TIM2: (Tim2en, Tim2rst, Tim2lpen),
+    TIM3: (Tim3en, Tim3rst, Tim3lpen),
+    TIM4: (Tim4en, Tim4rst, Tim4lpen),
+    TIM5: (Tim5en, Tim5rst, Tim5lpen),
+    WWDG: (Wwdgen, Wwdgrst, Wwdglpen),
+    spi::SPI2: (Spi2en, Spi2rst, Spi2lpen),
+    spi::SPI3: (Spi3en, Spi3rst, Spi3lpen),
+    USART2: (Usart2en, Usart2rst, Usart2lpen),
+    I2C1: (I2c1en, I2c1rst, I2c1lpen),
+    I2C2: (I2c2en, I2c2rst, I2c2lpen),
+    I2C3: (I2c3en, I2c3rst, I2c3lpen),
+    pwr::PWR: (Pwren, Pwrrst, Pwrlpen),
+]);
+
+bus!(apb2enr, apb2rstr, apb2lpenr: [
+    TIM1: (Tim1en, Tim1rst, Tim1lpen),
+    USART1: (Usart1en, Usart1rst, Usart1lpen),
+    USART6: (Usart6en, Usart6rst, Usart6lpen),
+    ADC1: (Adc1en, Adc1rst, Adc1lpen),
+    SDIO: (Sdioen, Sdiorst, Sdiolpen),
+    spi::SPI1: (Spi1en, Spi1rst, Spi1lpen),
+    spi::SPI4: (Spi4en, Spi4rst, Spi4lpen),
+    syscfg::SYSCFG: (Syscfgen, Syscfgrst, Syscfglpen),
+    TIM9: (Tim9en, Tim9rst, Tim9lpen),
+    TIM10: (Tim10en, Tim10rst, Tim10lpen),
+    TIM11: (Tim11en, Tim11rst, Tim11lpen),
+]);
+
+/// Stops the clock of every peripheral, the flash interface and SRAM1 during Sleep mode
+///
+/// Peripherals that must keep running or wake the core are then re-enabled one by one
+/// with `Enable::enable_in_sleep`. All clocks stay on in Sleep mode after reset.
+pub fn disable_all_in_sleep(rcc: &RCC) {
+    unsafe {
+        rcc.ahb1lpenr.write(0);
+        rcc.ahb2lpenr.write(0);
+        rcc.apb1lpenr.write(0);
+        rcc.apb2lpenr.write(0);
+    }
+}
+
+/// Clock routed to an MCO pin for as long as the guard lives
+///
+/// The pin is held by the guard in alternate function 0. `free`, or dropping the
+/// guard, restores the MCO source and prescaler and switches the pin back to `MODE`.
+pub struct Mco<'a, PORT: gpio::PortId + 'a, N: gpio::PinIndex + 'a, MODE: gpio::PinMode + 'a> {
+    rcc: &'a RCC,
+    pin: Option<gpio::Pin<PORT, N, gpio::Alternate<gpio::AF0>>>,
+    cfgr_mask: u32,
+    cfgr: u32,
+    _mode: PhantomData<MODE>,
+}
+
+impl<'a, PORT, N, MODE> Mco<'a, PORT, N, MODE>
+where
+    PORT: gpio::PortId,
+    N: gpio::PinIndex,
+    MODE: gpio::PinMode,
+{
+    /// Outputs `source` divided by `prescaler` on MCO1 (PA8)
+    pub fn mco1(
+        rcc: &'a RCC,
+        pin: gpio::Pin<PORT, N, MODE>,
+        source: cfgr::Mco1,
+        prescaler: cfgr::Moc1pre,
+    ) -> Mco<'a, PORT, N, MODE>
+    where
+        gpio::Pin<PORT, N, gpio::Alternate<gpio::AF0>>: af::McoPin<af::MCO1>,
+    {
+        Mco::new(
+            rcc,
+            pin,
+            cfgr::MCO1_MASK | cfgr::MCO1PRE_MASK,
+            source as u32 | prescaler as u32,
+        )
+    }
+
+    /// Outputs `source` divided by `prescaler` on MCO2 (PC9)
+    pub fn mco2(
+        rcc: &'a RCC,
+        pin: gpio::Pin<PORT, N, MODE>,
+        source: cfgr::Moc2,
+        prescaler: cfgr::Moc2pre,
+    ) -> Mco<'a, PORT, N, MODE>
+    where
+        gpio::Pin<PORT, N, gpio::Alternate<gpio::AF0>>: af::McoPin<af::MCO2>,
+    {
+        Mco::new(
+            rcc,
+            pin,
+            cfgr::MCO2_MASK | cfgr::MCO2PRE_MASK,
+            source as u32 | prescaler as u32,
+        )
+    }
+
+    /// Stops routing the clock and hands the pin back in its original mode
+    ///
+    /// The pin keeps the high output speed set for the clock output.
+    pub fn free(mut self) -> gpio::Pin<PORT, N, MODE> {
+        self.release().unwrap()
+    }
+
+    fn new(
+        rcc: &'a RCC,
+        pin: gpio::Pin<PORT, N, MODE>,
+        cfgr_mask: u32,
+        cfgr: u32,
+    ) -> Mco<'a, PORT, N, MODE> {
+        let previous = rcc.cfgr.read() & cfgr_mask;
+
+        // Select the clock before the pin starts driving it
+        unsafe { rcc.cfgr.modify(|v| (v & !cfgr_mask) | cfgr) }
+        let mut pin = pin.into_alternate::<gpio::AF0>();
+        pin.set_speed(gpio::ospeedr::Ospeedr::High);
+
+        Mco {
+            rcc,
+            pin: Some(pin),
+            cfgr_mask,
+            cfgr: previous,
+            _mode: PhantomData,
+        }
+    }
+
+    fn release(&mut self) -> Option<gpio::Pin<PORT, N, MODE>> {
+        self.pin.take().map(|pin| {
+            let pin = pin.into_mode();
+            unsafe { self.rcc.cfgr.modify(|v| (v & !self.cfgr_mask) | self.cfgr) }
+            pin
+        })
+    }
+}
+
+impl<'a, PORT, N, MODE> Drop for Mco<'a, PORT, N, MODE>
+where
+    PORT: gpio::PortId,
+    N: gpio::PinIndex,
+    MODE: gpio::PinMode,
+{
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Frequency of the internal high-speed oscillator
+pub const HSI_FREQUENCY: u32 = 16_000_000;
+
+/// Maximum SYSCLK and HCLK frequency of the STM32F401
+pub const SYSCLK_MAX: u32 = 84_000_000;
+/// Maximum APB1 (low-speed) clock frequency
+pub const PCLK1_MAX: u32 = 42_000_000;
+/// Maximum APB2 (high-speed) clock frequency
+pub const PCLK2_MAX: u32 = 84_000_000;
+/// Frequency required by USB OTG FS, SDIO and RNG on the PLL48CLK output
+pub const PLL48CLK_FREQUENCY: u32 = 48_000_000;
+/// Frequency the RTC expects from the HSE division
+pub const RTC_HSE_FREQUENCY: u32 = 1_000_000;
+
+/// Number of status polls before an oscillator, the PLL or the clock switch is
+/// reported as not ready (over 100 ms at 16 MHz)
+pub const READY_TIMEOUT: u32 = 0x0010_0000;
+/// Number of status polls before LSE is reported as not ready, as a 32.768 kHz
+/// crystal takes up to 2 s to start (over 2 s at 16 MHz)
+pub const LSE_READY_TIMEOUT: u32 = 0x0200_0000;
+
+const HSE_MIN: u32 = 4_000_000;
+const HSE_MAX: u32 = 26_000_000;
+const HSE_BYPASS_MIN: u32 = 1_000_000;
+const HSE_BYPASS_MAX: u32 = 50_000_000;
+const PLL_INPUT_MIN: u32 = 1_000_000;
+const PLL_INPUT_MAX: u32 = 2_000_000;
+const PLL_VCO_MIN: u32 = 192_000_000;
+const PLL_VCO_MAX: u32 = 432_000_000;
+const PLLM_MIN: u32 = 2;
+const PLLM_MAX: u32 = 63;
+const PLLN_MIN: u32 = 192;
+const PLLN_MAX: u32 = 432;
+const PLLQ_MIN: u32 = 2;
+const PLLQ_MAX: u32 = 15;
+const PLLI2SN_MIN: u32 = 192;
+const PLLI2SN_MAX: u32 = 432;
+const PLLI2SR_MIN: u32 = 2;
+const PLLI2SR_MAX: u32 = 7;
+const I2SCLK_MAX: u32 = 192_000_000;
+const I2SDIV_MIN: u32 = 2;
+const I2SDIV_MAX: u32 = 255;
+const SAMPLE_RATE_MIN: u32 = 8_000;
+const SAMPLE_RATE_MAX: u32 = 192_000;
+const MODULATION_MAX: u32 = 10_000;
+const MODULATION_DEPTH_MAX: u32 = 200;
+const MODPER_MAX: u32 = 0x1FFF;
+const INCSTEP_MAX: u32 = 0x7FFF;
+
+/// Reason why a clock configuration was rejected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The HSE frequency is outside of the 4 to 26 MHz crystal range
+    /// or of the 1 to 50 MHz external clock range
+    HseOutOfRange(u32),
+    /// HSI did not become ready
+    HsiTimeout,
+    /// HSE did not become ready, the crystal or the external clock is missing
+    HseTimeout,
+    /// The PLL or PLLI2S did not lock or unlock
+    PllTimeout,
+    /// The regulator did not reach the requested voltage scale
+    VoltageScaleTimeout,
+    /// The system clock switch was not acknowledged
+    SwitchTimeout,
+    /// The clock security system only monitors HSE
+    CssRequiresHse,
+    /// The audio sample rate is outside of 8 to 192 kHz or no PLLI2S factors reach it
+    SampleRateUnreachable(u32),
+    /// Spread spectrum modulation needs SYSCLK from the main PLL
+    SpreadSpectrumRequiresPll,
+    /// The modulation frequency or depth is out of range, or MODPER x INCSTEP
+    /// exceeds 2^15 - 1
+    SpreadSpectrumOutOfRange,
+    /// LSI did not become ready
+    LsiTimeout,
+    /// LSE did not become ready, the crystal or the external clock is missing
+    LseTimeout,
+    /// Another RTC clock is already selected, which only a backup domain reset undoes
+    RtcClockLocked,
+    /// The HSE division factor for the RTC clock is outside of 2 to 31
+    RtcPrescalerOutOfRange(u32),
+    /// The oscillator selected as RTC clock is not running
+    RtcClockNotReady,
+    /// HSE does not drive the clock tree, or HSE divided by the given factor is not 1 MHz
+    RtcPrescalerMismatch(u32),
+    /// The requested SYSCLK is above 84 MHz
+    SysclkTooHigh(u32),
+    /// No PLL factors within the VCO limits produce a SYSCLK close to the request
+    SysclkUnreachable(u32),
+    /// No PLL factors produce both the requested SYSCLK and exactly 48 MHz on PLL48CLK
+    Pll48clkUnreachable,
+    /// The requested HCLK is below SYSCLK / 512
+    HclkUnreachable(u32),
+    /// The requested PCLK1 is above 42 MHz or below HCLK / 16
+    Pclk1Unreachable(u32),
+    /// The requested PCLK2 is above 84 MHz or below HCLK / 16
+    Pclk2Unreachable(u32),
+}
+
+/// Supply voltage range of the board, which limits the flash access frequency
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoltageRange {
+    /// 1.7 V to 2.1 V, prefetch is not available
+    Vdd1V7To2V1,
+    /// 2.1 V to 2.4 V
+    Vdd2V1To2V4,
+    /// 2.4 V to 2.7 V
+    Vdd2V4To2V7,
+    /// 2.7 V to 3.6 V
+    Vdd2V7To3V6,
+}
+
+/// Spread spectrum modulation profile of the main PLL
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spread {
+    /// The frequency swings around the nominal PLL output
+    Center = 0b0 << 30,
+    /// The frequency swings below the nominal PLL output
+    Down = 0b1 << 30,
+}
+
+/// Oscillator that feeds SYSCLK directly or through the PLL
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Hsi,
+    /// HSE of the given frequency
+    Hse(u32, HseMode),
+}
+
+/// How the HSE input is driven
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HseMode {
+    /// Crystal or ceramic resonator between OSC_IN and OSC_OUT
+    Crystal,
+    /// External clock on OSC_IN, e.g. the 8 MHz MCO of the Nucleo ST-LINK
+    Bypass,
+}
+
+/// Clock tree configuration builder
+///
+/// SYSCLK defaults to the source frequency, HCLK to SYSCLK and PCLK1/PCLK2 to HCLK
+/// limited to their maximum. Requested frequencies are upper bounds: the closest
+/// achievable frequency that does not exceed the request is used.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    source: Source,
+    sysclk: Option<u32>,
+    hclk: Option<u32>,
+    pclk1: Option<u32>,
+    pclk2: Option<u32>,
+    pll48clk: bool,
+    timpre: dckcfgr::Timpre,
+    voltage: VoltageRange,
+    css: Option<u32>,
+    i2s: Option<(u32, bool, spi::i2scfgr::Chlen)>,
+    spread_spectrum: Option<(Spread, u32, u32)>,
+}
+
+/// Frozen clock frequencies
+///
+/// Returned by `Config::freeze` once the clock tree is running. Drivers take it to
+/// compute their dividers from target frequencies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clocks {
+    sysclk: u32,
+    hclk: u32,
+    pclk1: u32,
+    pclk2: u32,
+    timclk1: u32,
+    timclk2: u32,
+    pll48clk: Option<u32>,
+    i2s: Option<I2sClock>,
+    hse: Option<u32>,
+}
+
+impl Clocks {
+    /// System clock frequency
+    pub fn sysclk(&self) -> u32 {
+        self.sysclk
+    }
+
+    /// AHB clock frequency, which also drives the core and SysTick
+    pub fn hclk(&self) -> u32 {
+        self.hclk
+    }
+
+    /// APB1 clock frequency
+    pub fn pclk1(&self) -> u32 {
+        self.pclk1
+    }
+
+    /// APB2 clock frequency
+    pub fn pclk2(&self) -> u32 {
+        self.pclk2
+    }
+
+    /// Clock of the timers on APB1 (TIM2 to TIM5)
+    pub fn timclk1(&self) -> u32 {
+        self.timclk1
+    }
+
+    /// Clock of the timers on APB2 (TIM1, TIM9 to TIM11)
+    pub fn timclk2(&self) -> u32 {
+        self.timclk2
+    }
+
+    /// PLL48CLK frequency, if the main PLL is running
+    pub fn pll48clk(&self) -> Option<u32> {
+        self.pll48clk
+    }
+
+    /// I2S clock frequency, if PLLI2S is running
+    pub fn i2sclk(&self) -> Option<u32> {
+        self.i2s.map(|i2s| i2s.i2sclk)
+    }
+
+    /// PLLI2S and I2S prescaler settings, if an audio sample rate was requested
+    pub fn i2s(&self) -> Option<I2sClock> {
+        self.i2s
+    }
+
+    /// HSE frequency, if the clock tree runs from HSE
+    pub fn hse(&self) -> Option<u32> {
+        self.hse
+    }
+}
+
+/// PLLI2S and I2S prescaler settings found for an audio sample rate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct I2sClock {
+    m: u32,
+    n: u32,
+    r: u32,
+    div: u32,
+    odd: bool,
+    mclk: bool,
+    i2sclk: u32,
+    sample_rate_millihertz: u64,
+}
+
+impl I2sClock {
+    /// PLLI2S multiplication factor
+    pub fn plli2sn(&self) -> u32 {
+        self.n
+    }
+
+    /// PLLI2S division factor for the I2S clock
+    pub fn plli2sr(&self) -> u32 {
+        self.r
+    }
+
+    /// Linear prescaler of the I2S peripheral
+    pub fn i2sdiv(&self) -> u32 {
+        self.div
+    }
+
+    /// Odd factor of the I2S prescaler
+    pub fn odd(&self) -> bool {
+        self.odd
+    }
+
+    /// Achieved sample rate in Hz, rounded to the nearest integer
+    pub fn sample_rate(&self) -> u32 {
+        ((self.sample_rate_millihertz + 500) / 1000) as u32
+    }
+
+    /// Achieved sample rate in mHz
+    pub fn sample_rate_millihertz(&self) -> u64 {
+        self.sample_rate_millihertz
+    }
+
+    /// Value to write into the I2SPR register of the I2S peripheral
+    pub fn i2spr(&self) -> u32 {
+        let odd = if self.odd {
+            spi::i2spr::Odd::I2sDivX2P1
+        } else {
+            spi::i2spr::Odd::I2sDivX2
+        };
+        let mckoe = if self.mclk {
+            spi::i2spr::Mckoe::Enable
+        } else {
+            spi::i2spr::Mckoe::Disable
+        };
+        self.div | odd as u32 | mckoe as u32
+    }
+}
+
+/// How the LSE input is driven
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LseMode {
+    /// 32.768 kHz crystal between OSC32_IN and OSC32_OUT
+    Crystal(bdcr::Lsemod),
+    /// External clock on OSC32_IN
+    Bypass,
+}
+
+/// Clock of the RTC and of the backup domain timekeeping
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RtcClock {
+    Lse,
+    Lsi,
+    /// HSE divided by the given factor (2 to 31), which must result in 1 MHz from the
+    /// HSE frequency of the frozen clock tree
+    Hse(u32),
+}
+
+/// Starts the internal 32 kHz oscillator, used by the independent watchdog and the RTC
+pub fn enable_lsi(rcc: &RCC) -> Result<(), Error> {
+    unsafe { rcc.csr.modify(|v| v | csr::Lsion::On as u32) }
+    wait_for(
+        || rcc.csr.read() & csr::Lsirdy::Ready as u32 == csr::Lsirdy::Ready as u32,
+        Error::LsiTimeout,
+    )
+}
+
+/// Stops the internal 32 kHz oscillator
+pub fn disable_lsi(rcc: &RCC) {
+    unsafe { rcc.csr.modify(|v| v & !(csr::Lsion::On as u32)) }
+}
+
+/// Write access to the backup domain: LSE, RTC clock selection and RTC enable
+///
+/// The backup domain is write protected after reset. It stays writable until the
+/// access is dropped. The access borrows PWR mutably, so only one can exist at a time
+/// and no other access can clear DBP under it.
+pub struct BackupDomain<'a> {
+    rcc: &'a RCC,
+    pwr: &'a mut pwr::PWR,
+}
+
+impl<'a> BackupDomain<'a> {
+    /// Enables the PWR clock and unlocks backup domain writes with DBP
+    pub fn unlock(rcc: &'a RCC, pwr: &'a mut pwr::PWR) -> BackupDomain<'a> {
+        pwr::PWR::enable(rcc);
+        unsafe { pwr.cr.modify(|v| v | pwr::cr::DBP) }
+        BackupDomain { rcc, pwr }
+    }
+
+    /// Resets the whole backup domain, stopping LSE and the RTC and clearing the RTC
+    /// clock selection and the RTC backup registers
+    pub fn reset(&mut self) {
+        unsafe {
+            self.rcc.bdcr.modify(|v| v | bdcr::BDRST);
+            self.rcc.bdcr.modify(|v| v & !bdcr::BDRST);
+        }
+    }
+
+    /// Starts LSE and waits at most `LSE_READY_TIMEOUT` polls for it
+    ///
+    /// LSE is stopped again when it does not become ready.
+    pub fn enable_lse(&mut self, mode: LseMode) -> Result<(), Error> {
+        let bdcr = self.rcc.bdcr.read();
+        let bits = match mode {
+            LseMode::Crystal(lsemod) => lsemod as u32,
+            LseMode::Bypass => bdcr::Lsebyp::Bypassed as u32,
+        };
+        let mode_mask = bdcr::LSEMOD_MASK | bdcr::Lsebyp::Bypassed as u32;
+
+        // LSEBYP and LSEMOD can only be written while LSE is off
+        if bdcr & bdcr::Lseon::On as u32 != 0 && bdcr & mode_mask != bits {
+            self.disable_lse()?;
+        }
+        unsafe {
+            self.rcc.bdcr.modify(|v| (v & !mode_mask) | bits);
+            self.rcc.bdcr.modify(|v| v | bdcr::Lseon::On as u32);
+        }
+
+        let rcc = self.rcc;
+        let ready = poll(
+            || rcc.bdcr.read() & bdcr::Lserdy::Ready as u32 == bdcr::Lserdy::Ready as u32,
+            LSE_READY_TIMEOUT,
+            Error::LseTimeout,
+        );
+        if ready.is_err() {
+            unsafe { self.rcc.bdcr.modify(|v| v & !(bdcr::Lseon::On as u32)) }
+        }
+        ready
+    }
+
+    /// Stops LSE and waits for it to be off
+    pub fn disable_lse(&mut self) -> Result<(), Error> {
+        unsafe { self.rcc.bdcr.modify(|v| v & !(bdcr::Lseon::On as u32)) }
+        let rcc = self.rcc;
+        wait_for(
+            || rcc.bdcr.read() & bdcr::Lserdy::Ready as u32 == bdcr::Lserdy::NotReady as u32,
+            Error::LseTimeout,
+        )
+    }
+
+    /// Selects the RTC clock
+    ///
+    /// The selection can only be made once, choosing another clock afterwards needs a
+    /// backup domain `reset`. The selected oscillator must already be running, and HSE
+    /// must drive the clock tree frozen by `Config::freeze` for the divider to be checked.
+    pub fn select_rtc_clock(&mut self, clock: RtcClock) -> Result<(), Error> {
+        let (rtcsel, ready) = match clock {
+            RtcClock::Lse => (
+                bdcr::Rtcsel::Lse,
+                self.rcc.bdcr.read() & bdcr::Lserdy::Ready as u32 == bdcr::Lserdy::Ready as u32,
+            ),
+            RtcClock::Lsi => (
+                bdcr::Rtcsel::Lsi,
+                self.rcc.csr.read() & csr::Lsirdy::Ready as u32 == csr::Lsirdy::Ready as u32,
+            ),
+            RtcClock::Hse(div) if div >= 2 && div <= 31 => (
+                bdcr::Rtcsel::Hse,
+                self.rcc.cr.read() & cr::Hserdy::Ready as u32 == cr::Hserdy::Ready as u32,
+            ),
+            RtcClock::Hse(div) => return Err(Error::RtcPrescalerOutOfRange(div)),
+        };
+        let rtcsel = rtcsel as u32;
+
+        if !ready {
+            return Err(Error::RtcClockNotReady);
+        }
+        if let RtcClock::Hse(div) = clock {
+            match current_clocks().and_then(|clocks| clocks.hse()) {
+                Some(hse) if hse == div * RTC_HSE_FREQUENCY => {}
+                _ => return Err(Error::RtcPrescalerMismatch(div)),
+            }
+        }
+
+        let current = self.rcc.bdcr.read() & bdcr::RTCSEL_MASK;
+        if current != bdcr::Rtcsel::NoClock as u32 && current != rtcsel {
+            return Err(Error::RtcClockLocked);
+        }
+        if let RtcClock::Hse(div) = clock {
+            unsafe {
+                self.rcc.cfgr.modify(|v| {
+                    (v & !cfgr::RTCPRE_MASK) | div << cfgr::RTCPRE_SHIFT
+                })
+            }
+        }
+        unsafe { self.rcc.bdcr.modify(|v| (v & !bdcr::RTCSEL_MASK) | rtcsel) }
+        Ok(())
+    }
+
+    /// Starts the RTC clock
+    pub fn enable_rtc(&mut self) {
+        unsafe { self.rcc.bdcr.modify(|v| v | bdcr::RTCEN) }
+    }
+
+    /// Stops the RTC clock
+    pub fn disable_rtc(&mut self) {
+        unsafe { self.rcc.bdcr.modify(|v| v & !bdcr::RTCEN) }
+    }
+}
+
+impl<'a> Drop for BackupDomain<'a> {
+    fn drop(&mut self) {
+        unsafe { self.pwr.cr.modify(|v| v & !pwr::cr::DBP) }
+    }
+}
+
+/// Cause of the last reset, decoded from the RCC_CSR flags
+///
+/// Several flags are set by one event, e.g. a power-on reset also sets the BOR and pin
+/// flags, so the most specific cause is reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetReason {
+    /// Power-on or power-down reset
+    PowerOn,
+    /// Brownout reset
+    Brownout,
+    /// Low-power management reset, entering Standby or Stop with nRST_STDBY/nRST_STOP cleared
+    LowPower,
+    /// Window watchdog reset
+    WindowWatchdog,
+    /// Independent watchdog reset
+    IndependentWatchdog,
+    /// Software reset through SCB AIRCR SYSRESETREQ
+    Software,
+    /// Reset from the NRST pin
+    Pin,
+    /// No reset flag was set
+    Unknown,
+}
+
+static mut RESET_REASON: Option<ResetReason> = None;
+
+/// Returns the cause of the last reset
+///
+/// The first call decodes RCC_CSR and clears the flags with RMVF, so that the next
+/// reset is reported alone. Later calls return the same cause.
+pub fn reset_reason(rcc: &RCC) -> ResetReason {
+    cortex_m::interrupt::free(|_| unsafe {
+        if let Some(reason) = RESET_REASON {
+            return reason;
+        }
+
+        let flags = rcc.csr.read();
+        rcc.csr.modify(|v| v | csr::RMVF);
+
+        let reason = if flags & csr::PORRSTF != 0 {
+            ResetReason::PowerOn
+        } else if flags & csr::BORRSTF != 0 {
+            ResetReason::Brownout
+        } else if flags & csr::LPWRRSTF != 0 {
+            ResetReason::LowPower
+        } else if flags & csr::WWDGRSTF != 0 {
+            ResetReason::WindowWatchdog
+        } else if flags & csr::IWDGRSTF != 0 {
+            ResetReason::IndependentWatchdog
+        } else if flags & csr::SFTRSTF != 0 {
+            ResetReason::Software
+        } else if flags & csr::PINRSTF != 0 {
+            ResetReason::Pin
+        } else {
+            ResetReason::Unknown
+        };
+        RESET_REASON = Some(reason);
+        reason
+    })
+}
+
+static CLOCKS_SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+static mut CLOCKS: Option<Clocks> = None;
+static mut CSS_FALLBACK: Option<Config> = None;
+static mut CSS_CALLBACK: Option<fn(&Clocks)> = None;
+
+/// Returns the clock frequencies currently in effect
+///
+/// This is the record of the last `Config::freeze`, written from thread mode with
+/// interrupts masked, or from the NMI handler after a clock failure. Safe to call
+/// from any interrupt handler. `None` until the clock tree has been configured.
+pub fn current_clocks() -> Option<Clocks> {
+    // The record may be rewritten while it is being copied, by `freeze` in the NMI
+    // handler after a clock failure. Thread mode publishes with interrupts masked, so
+    // only the NMI can preempt a writer and a retry always finds an even sequence.
+    loop {
+        let sequence = CLOCKS_SEQUENCE.load(Ordering::Acquire);
+        let clocks = unsafe { ptr::read_volatile(&CLOCKS) };
+        if sequence & 1 == 0 && CLOCKS_SEQUENCE.load(Ordering::Acquire) == sequence {
+            return clocks;
+        }
+    }
+}
+
+/// Registers a function called from the NMI handler once the clock tree has
+/// recovered from an HSE failure, e.g. to recompute baud rates
+pub fn on_clock_failure(callback: fn(&Clocks)) {
+    unsafe { ptr::write_volatile(&mut CSS_CALLBACK, Some(callback)) }
+}
+
+fn publish_clocks(clocks: Clocks) {
+    // An interrupt handler reading the record must not preempt the write, it would
+    // spin on the odd sequence forever. The NMI cannot be masked, but CSS is off
+    // whenever thread mode publishes, so no clock failure NMI can interrupt it.
+    cortex_m::interrupt::free(|_| {
+        CLOCKS_SEQUENCE.fetch_add(1, Ordering::AcqRel);
+        unsafe { ptr::write_volatile(&mut CLOCKS, Some(clocks)) }
+        CLOCKS_SEQUENCE.fetch_add(1, Ordering::AcqRel);
+    })
+}
+
+// NMI defaults to the clock failure handler, an application that needs other
+// NMI sources defines its own `NMI` and calls `css_nmi_handler` from it
+global_asm!(
+    ".weak NMI
+    NMI = css_nmi_handler"
+);
+
+/// Recovers from an HSE failure detected by the clock security system
+///
+/// Any other NMI source is still pending on return and would re-enter the handler, so
+/// the core halts at a breakpoint and spins there instead.
+#[no_mangle]
+pub extern "C" fn css_nmi_handler() {
+    let rcc = unsafe { RCC::new() };
+    if rcc.cir.read() & cir::CSSF == 0 {
+        loop {
+            asm::bkpt();
+        }
+    }
+
+    // The NMI stays pending until CSSF is cleared
+    unsafe { rcc.cir.modify(|v| v | cir::CSSC) }
+
+    if let Some(config) = unsafe { CSS_FALLBACK.take() } {
+        let (flash, pwr) = unsafe { (flash::FLASH::new(), pwr::PWR::new()) };
+        if let Ok(clocks) = config.freeze(&rcc, &flash, &pwr) {
+            if let Some(callback) = unsafe { ptr::read_volatile(&CSS_CALLBACK) } {
+                callback(&clocks);
+            }
+        }
+    }
+}
+
+/// PLL factors found for a configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PllFactors {
+    m: u32,
+    n: u32,
+    p: u32,
+    q: u32,
+}
+
+/// Register level settings computed from a configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Setup {
+    source_frequency: u32,
+    pll: Option<PllFactors>,
+    sscgr: u32,
+    i2s: Option<I2sClock>,
+    sysclk: u32,
+    hpre: u32,
+    ppre1: u32,
+    ppre2: u32,
+}
+
+impl Config {
+    /// Starts a configuration clocked from the 16 MHz HSI
+    pub fn hsi() -> Config {
+        Config::new(Source::Hsi)
+    }
+
+    /// Starts a configuration clocked from an HSE crystal of `frequency` Hz
+    pub fn hse(frequency: u32) -> Config {
+        Config::new(Source::Hse(frequency, HseMode::Crystal))
+    }
+
+    /// Starts a configuration clocked from an external clock of `frequency` Hz on OSC_IN
+    pub fn hse_bypass(frequency: u32) -> Config {
+        Config::new(Source::Hse(frequency, HseMode::Bypass))
+    }
+
+    fn new(source: Source) -> Config {
+        Config {
+            source,
+            sysclk: None,
+            hclk: None,
+            pclk1: None,
+            pclk2: None,
+            pll48clk: false,
+            timpre: dckcfgr::Timpre::X2,
+            voltage: VoltageRange::Vdd2V7To3V6,
+            css: None,
+            i2s: None,
+            spread_spectrum: None,
+        }
+    }
+
+    /// Requests the system clock frequency
+    pub fn sysclk(mut self, frequency: u32) -> Config {
+        self.sysclk = Some(frequency);
+        self
+    }
+
+    /// Requests the AHB clock frequency
+    pub fn hclk(mut self, frequency: u32) -> Config {
+        self.hclk = Some(frequency);
+        self
+    }
+
+    /// Requests the APB1 clock frequency
+    pub fn pclk1(mut self, frequency: u32) -> Config {
+        self.pclk1 = Some(frequency);
+        self
+    }
+
+    /// Requests the APB2 clock frequency
+    pub fn pclk2(mut self, frequency: u32) -> Config {
+        self.pclk2 = Some(frequency);
+        self
+    }
+
+    /// Requires exactly 48 MHz on PLL48CLK for USB OTG FS, SDIO and RNG
+    pub fn require_pll48clk(mut self) -> Config {
+        self.pll48clk = true;
+        self
+    }
+
+    /// Selects the timer clock multiplier applied to PCLK1/PCLK2
+    pub fn timpre(mut self, timpre: dckcfgr::Timpre) -> Config {
+        self.timpre = timpre;
+        self
+    }
+
+    /// Sets the supply voltage range used to select the flash wait states
+    ///
+    /// Defaults to 2.7 V to 3.6 V.
+    pub fn voltage(mut self, voltage: VoltageRange) -> Config {
+        self.voltage = voltage;
+        self
+    }
+
+    /// Runs PLLI2S for an audio sample rate between 8 and 192 kHz
+    ///
+    /// PLLI2S shares the input divider of the main PLL. The factors and the I2S
+    /// prescaler that minimise the sample rate error are reported by `Clocks::i2s`.
+    /// `chlen` only matters when MCLK is not output.
+    pub fn i2s(mut self, sample_rate: u32, mclk: bool, chlen: spi::i2scfgr::Chlen) -> Config {
+        self.i2s = Some((sample_rate, mclk, chlen));
+        self
+    }
+
+    /// Modulates the main PLL to spread its spectrum and reduce EMI peaks
+    ///
+    /// `modulation` is the modulation frequency in Hz, up to 10 kHz, and `depth` is the
+    /// peak modulation depth in 0.01 % units, up to 2 %. The achieved profile depends on
+    /// the rounding of MODPER and INCSTEP.
+    pub fn spread_spectrum(mut self, spread: Spread, modulation: u32, depth: u32) -> Config {
+        self.spread_spectrum = Some((spread, modulation, depth));
+        self
+    }
+
+    /// Enables the clock security system on HSE
+    ///
+    /// When HSE fails, the hardware switches SYSCLK to HSI and raises an NMI. The NMI
+    /// handler then runs the PLL from HSI at `fallback_sysclk`, keeping the requested
+    /// HCLK, PCLK1 and PCLK2 limits, updates `current_clocks` and calls the callback
+    /// registered with `on_clock_failure`.
+    pub fn css(mut self, fallback_sysclk: u32) -> Config {
+        self.css = Some(fallback_sysclk);
+        self
+    }
+
+    /// Configures the clock tree and returns the resulting frequencies
+    ///
+    /// The flash wait states and the regulator voltage scale follow the new HCLK. When
+    /// the clock is raised the wait states are increased before the switch, when it is
+    /// lowered they are decreased only after the switch.
+    ///
+    /// Every ready flag is polled at most `READY_TIMEOUT` times and a timeout error is
+    /// returned when one does not rise. The clock tree is not rolled back on an error:
+    ///
+    /// - if SYSCLK already runs from HSI, it stays there with the PLL and PLLI2S off and
+    ///   the AHB and APB prescalers found in CFGR, and `current_clocks` is updated to
+    ///   describe this state. The clock security system stays off.
+    /// - if the switch to HSI did not complete, the previous configuration keeps running,
+    ///   SW and CSSON are restored and `current_clocks` is left unchanged.
+    pub fn freeze(self, rcc: &RCC, flash: &flash::FLASH, pwr: &pwr::PWR) -> Result<Clocks, Error> {
+        let setup = self.setup()?;
+        let hclk = setup.sysclk / setup.hpre;
+        let latency = flash_latency(hclk, self.voltage);
+        let raising = latency > flash.acr.read() & flash::acr::LATENCY_MASK;
+
+        // HSE may be stopped below, which must not be reported as a failure
+        let csson = rcc.cr.read() & cr::Csson::On as u32;
+        unsafe { rcc.cr.modify(|v| v & !(cr::Csson::On as u32)) }
+
+        if raising {
+            set_flash_latency(flash, latency, self.voltage);
+        }
+
+        if let Err(error) = self.apply(rcc, pwr, &setup) {
+            if rcc.cfgr.read() & cfgr::SWS_MASK == cfgr::Sws::HsiOscillator as u32 {
+                unsafe {
+                    rcc.cr.modify(|v| v & !(cr::PLLON_MASK | cr::Plli2son::On as u32));
+                }
+                publish_clocks(hsi_clocks(rcc));
+            } else {
+                unsafe {
+                    rcc.cfgr.modify(|v| (v & !cfgr::SW_MASK) | (v & cfgr::SWS_MASK) >> 2);
+                    rcc.cr.modify(|v| v | csson);
+                }
+            }
+            return Err(error);
+        }
+
+        if !raising {
+            set_flash_latency(flash, latency, self.voltage);
+        }
+
+        let clocks = self.clocks(&setup);
+        publish_clocks(clocks);
+
+        if let Some(fallback_sysclk) = self.css {
+            unsafe {
+                CSS_FALLBACK = Some(self.fallback(fallback_sysclk));
+                rcc.cr.modify(|v| v | cr::Csson::On as u32);
+            }
+        }
+
+        Ok(clocks)
+    }
+
+    /// Programs the oscillators, the PLLs and the prescalers of `setup`
+    fn apply(&self, rcc: &RCC, pwr: &pwr::PWR, setup: &Setup) -> Result<(), Error> {
+        let hclk = setup.sysclk / setup.hpre;
+
+        // Run from HSI while the PLL and the selected oscillator are being (re)configured
+        enable_hsi(rcc)?;
+        switch_sysclk(rcc, cfgr::Sw::HsiOscillator, cfgr::Sws::HsiOscillator)?;
+
+        unsafe { rcc.cr.modify(|v| v & !cr::PLLON_MASK) }
+        wait_for(
+            || rcc.cr.read() & cr::Pllrdy::Locked as u32 == cr::Pllrdy::Unlocked as u32,
+            Error::PllTimeout,
+        )?;
+
+        // PLLM and PLLSRC are shared with PLLI2S, which must be off while they change
+        unsafe { rcc.cr.modify(|v| v & !(cr::Plli2son::On as u32)) }
+        wait_for(
+            || rcc.cr.read() & cr::Plli2srdy::Locked as u32 == cr::Plli2srdy::Unlocked as u32,
+            Error::PllTimeout,
+        )?;
+
+        let pllsrc = match self.source {
+            Source::Hsi => pllcfgr::Pllsrc::HsiClock,
+            Source::Hse(_, mode) => {
+                enable_hse(rcc, mode)?;
+                pllcfgr::Pllsrc::HseOscillatorClock
+            }
+        };
+
+        // VOS can only be modified while the PLL is off
+        pwr::PWR::enable(rcc);
+        unsafe {
+            pwr.cr.modify(|v| (v & !pwr::cr::VOS_MASK) | voltage_scale(hclk) as u32);
+        }
+
+        // SSCGR must be written before the PLL is enabled
+        unsafe { rcc.sscgr.write(setup.sscgr) }
+
+        if let Some(pll) = setup.pll {
+            unsafe {
+                rcc.pllcfgr.modify(|v| {
+                    (v &
+                         !(pllcfgr::PLLQ_MASK | pllcfgr::PLLSRC_MASK | pllcfgr::PLLP_MASK |
+                               pllcfgr::PLLN_MASK | pllcfgr::PLLM_MASK)) |
+                        pll.q << pllcfgr::PLLQ_SHIFT | pllsrc as u32 |
+                        pllp_bits(pll.p) | pll.n << pllcfgr::PLLN_SHIFT | pll.m
+                });
+                rcc.cr.modify(|v| v | cr::PLLON_MASK);
+            }
+            wait_for(
+                || rcc.cr.read() & cr::Pllrdy::Locked as u32 == cr::Pllrdy::Locked as u32,
+                Error::PllTimeout,
+            )?;
+            // The new voltage scale is applied once the PLL is on
+            wait_for(
+                || {
+                    pwr.csr.read() & pwr::csr::Vosrdy::Ready as u32 ==
+                        pwr::csr::Vosrdy::Ready as u32
+                },
+                Error::VoltageScaleTimeout,
+            )?;
+        }
+
+        if let Some(i2s) = setup.i2s {
+            unsafe {
+                if setup.pll.is_none() {
+                    rcc.pllcfgr.modify(|v| {
+                        (v & !(pllcfgr::PLLSRC_MASK | pllcfgr::PLLM_MASK)) | pllsrc as u32 | i2s.m
+                    });
+                }
+                rcc.plli2scfgr.modify(|v| {
+                    (v & !(plli2scfgr::PLLI2SR_MASK | plli2scfgr::PLLI2SN_MASK)) |
+                        i2s.r << plli2scfgr::PLLI2SR_SHIFT |
+                        i2s.n << plli2scfgr::PLLI2SN_SHIFT
+                });
+                rcc.cfgr.modify(|v| {
+                    (v & !cfgr::I2SSRC_MASK) | cfgr::I2ssrc::Plli2sClock as u32
+                });
+                rcc.cr.modify(|v| v | cr::Plli2son::On as u32);
+            }
+            wait_for(
+                || rcc.cr.read() & cr::Plli2srdy::Locked as u32 == cr::Plli2srdy::Locked as u32,
+                Error::PllTimeout,
+            )?;
+        }
+
+        // Keep the APB clocks within their limits while HCLK changes
+        unsafe {
+            rcc.cfgr.modify(|v| {
+                (v & !(cfgr::PPRE1_MASK | cfgr::PPRE2_MASK)) | cfgr::Ppre1::Div16 as u32 |
+                    cfgr::Ppre2::Div16 as u32
+            });
+            rcc.cfgr.modify(|v| (v & !cfgr::HPRE_MASK) | hpre_bits(setup.hpre));
+        }
+
+        match (setup.pll, self.source) {
+            (Some(_), _) => switch_sysclk(rcc, cfgr::Sw::Pll, cfgr::Sws::Pll)?,
+            (None, Source::Hse(..)) => {
+                switch_sysclk(rcc, cfgr::Sw::HseOscillator, cfgr::Sws::HseOscillator)?
+            }
+            (None, Source::Hsi) => {}
+        }
+
+        unsafe {
+            rcc.cfgr.modify(|v| {
+                (v & !(cfgr::PPRE1_MASK | cfgr::PPRE2_MASK)) | ppre1_bits(setup.ppre1) |
+                    ppre2_bits(setup.ppre2)
+            });
+            rcc.dckcfgr.modify(|v| (v & !dckcfgr::TIMPRE_MASK) | self.timpre as u32);
+        }
+
+        Ok(())
+    }
+
+    /// Configuration used after an HSE failure
+    fn fallback(&self, sysclk: u32) -> Config {
+        Config {
+            source: Source::Hsi,
+            sysclk: Some(sysclk),
+            pll48clk: false,
+            css: None,
+            ..*self
+        }
+    }
+
+    fn clocks(&self, setup: &Setup) -> Clocks {
+        let hclk = setup.sysclk / setup.hpre;
+        let pclk1 = hclk / setup.ppre1;
+        let pclk2 = hclk / setup.ppre2;
+
+        Clocks {
+            sysclk: setup.sysclk,
+            hclk,
+            pclk1,
+            pclk2,
+            timclk1: timer_clock(self.timpre, hclk, pclk1, setup.ppre1),
+            timclk2: timer_clock(self.timpre, hclk, pclk2, setup.ppre2),
+            pll48clk: setup.pll.map(|pll| {
+                (setup.source_frequency as u64 * pll.n as u64 / (pll.m as u64 * pll.q as u64)) as
+                    u32
+            }),
+            i2s: setup.i2s,
+            hse: match self.source {
+                Source::Hse(..) => Some(setup.source_frequency),
+                Source::Hsi => None,
+            },
+        }
+    }
+
+    fn source_frequency(&self) -> Result<u32, Error> {
+        match self.source {
+            Source::Hsi => Ok(HSI_FREQUENCY),
+            Source::Hse(frequency, HseMode::Crystal)
+                if frequency >= HSE_MIN && frequency <= HSE_MAX => Ok(frequency),
+            Source::Hse(frequency, HseMode::Bypass)
+                if frequency >= HSE_BYPASS_MIN && frequency <= HSE_BYPASS_MAX => Ok(frequency),
+            Source::Hse(frequency, _) => Err(Error::HseOutOfRange(frequency)),
+        }
+    }
+
+    fn setup(&self) -> Result<Setup, Error> {
+        let source_frequency = self.source_frequency()?;
+        if let Some(fallback_sysclk) = self.css {
+            if self.source == Source::Hsi {
+                return Err(Error::CssRequiresHse);
+            }
+            self.fallback(fallback_sysclk).setup()?;
+        }
+        let target = self.sysclk.unwrap_or(source_frequency);
+        if target > SYSCLK_MAX {
+            return Err(Error::SysclkTooHigh(target));
+        }
+
+        let (pll, sysclk) = if target == source_frequency && !self.pll48clk {
+            (None, source_frequency)
+        } else {
+            let pll = search_pll(source_frequency, target, self.pll48clk)?;
+            (Some(pll), pll_output(source_frequency, &pll))
+        };
+
+        let sscgr = match (self.spread_spectrum, pll) {
+            (None, _) => 0,
+            (Some(_), None) => return Err(Error::SpreadSpectrumRequiresPll),
+            (Some((spread, modulation, depth)), Some(pll)) => {
+                spread_spectrum(source_frequency / pll.m, pll.n, spread, modulation, depth)?
+            }
+        };
+
+        let i2s = match self.i2s {
+            Some((sample_rate, mclk, chlen)) => {
+                let m = pll.map(|pll| pll.m);
+                Some(search_plli2s(source_frequency, m, sample_rate, mclk, chlen)?)
+            }
+            None => None,
+        };
+
+        let hclk_target = self.hclk.unwrap_or(sysclk);
+        let hpre = select_divider(sysclk, hclk_target, &[1, 2, 4, 8, 16, 64, 128, 256, 512])
+            .ok_or(Error::HclkUnreachable(hclk_target))?;
+        let hclk = sysclk / hpre;
+
+        let pclk1_target = self.pclk1.unwrap_or(if hclk < PCLK1_MAX { hclk } else { PCLK1_MAX });
+        if pclk1_target > PCLK1_MAX {
+            return Err(Error::Pclk1Unreachable(pclk1_target));
+        }
+        let ppre1 = select_divider(hclk, pclk1_target, &[1, 2, 4, 8, 16])
+            .ok_or(Error::Pclk1Unreachable(pclk1_target))?;
+
+        let pclk2_target = self.pclk2.unwrap_or(if hclk < PCLK2_MAX { hclk } else { PCLK2_MAX });
+        if pclk2_target > PCLK2_MAX {
+            return Err(Error::Pclk2Unreachable(pclk2_target));
+        }
+        let ppre2 = select_divider(hclk, pclk2_target, &[1, 2, 4, 8, 16])
+            .ok_or(Error::Pclk2Unreachable(pclk2_target))?;
+
+        Ok(Setup {
+            source_frequency,
+            pll,
+            sscgr,
+            i2s,
+            sysclk,
+            hpre,
+            ppre1,
+            ppre2,
+        })
+    }
+}
+
+/// Searches the PLL factors whose output is closest to `target` without exceeding it
+///
+/// Lower PLLM values are preferred, as a higher VCO input frequency reduces PLL jitter.
+fn search_pll(input: u32, target: u32, pll48clk: bool) -> Result<PllFactors, Error> {
+    let m_min = cmp::max(PLLM_MIN, (input + PLL_INPUT_MAX - 1) / PLL_INPUT_MAX);
+    let m_max = cmp::min(PLLM_MAX, input / PLL_INPUT_MIN);
+
+    let mut best: Option<(PllFactors, u32)> = None;
+    for m in m_min..m_max + 1 {
+        let candidate = if pll48clk {
+            search_pll48(input, target, m)
+        } else {
+            search_plln(input, target, m)
+        };
+        if let Some(factors) = candidate {
+            let output = pll_output(input, &factors);
+            if best.map_or(true, |(_, best_output)| output > best_output) {
+                best = Some((factors, output));
+            }
+        }
+    }
+
+    match best {
+        Some((factors, _)) => Ok(factors),
+        None if pll48clk => Err(Error::Pll48clkUnreachable),
+        None => Err(Error::SysclkUnreachable(target)),
+    }
+}
+
+/// Searches PLLN and PLLP for a given PLLM, with PLL48CLK left unconstrained
+fn search_plln(input: u32, target: u32, m: u32) -> Option<PllFactors> {
+    let mut best: Option<(PllFactors, u32)> = None;
+    for &p in &[2, 4, 6, 8] {
+        // Largest PLLN whose output does not exceed the target
+        let n = (target as u64 * p as u64 * m as u64 / input as u64) as u32;
+        let n = cmp::min(n, PLLN_MAX);
+        if n < PLLN_MIN {
+            continue;
+        }
+        let vco = (input as u64 * n as u64 / m as u64) as u32;
+        if vco < PLL_VCO_MIN || vco > PLL_VCO_MAX {
+            continue;
+        }
+        // Keep PLL48CLK at or below 48 MHz even when it is unused
+        let q = cmp::max(PLLQ_MIN, (vco + PLL48CLK_FREQUENCY - 1) / PLL48CLK_FREQUENCY);
+        if q > PLLQ_MAX {
+            continue;
+        }
+
+        let factors = PllFactors { m, n, p, q };
+        let output = pll_output(input, &factors);
+        if best.map_or(true, |(_, best_output)| output > best_output) {
+            best = Some((factors, output));
+        }
+    }
+    best.map(|(factors, _)| factors)
+}
+
+/// Searches PLLN, PLLP and PLLQ for a given PLLM, with PLL48CLK at exactly 48 MHz
+///
+/// Every PLLQ fixes the VCO frequency to 48 MHz times PLLQ, so the valid (PLLN, PLLQ)
+/// pairs are enumerated and the one whose output gets closest to the target is kept.
+fn search_pll48(input: u32, target: u32, m: u32) -> Option<PllFactors> {
+    let mut best: Option<(PllFactors, u32)> = None;
+    for q in PLLQ_MIN..PLLQ_MAX + 1 {
+        let vco = PLL48CLK_FREQUENCY as u64 * q as u64;
+        if vco < PLL_VCO_MIN as u64 || vco > PLL_VCO_MAX as u64 {
+            continue;
+        }
+        // PLLN must be an integer for the VCO to land exactly on 48 MHz * PLLQ
+        if vco * m as u64 % input as u64 != 0 {
+            continue;
+        }
+        let n = (vco * m as u64 / input as u64) as u32;
+        if n < PLLN_MIN || n > PLLN_MAX {
+            continue;
+        }
+        for &p in &[2, 4, 6, 8] {
+            let factors = PllFactors { m, n, p, q };
+            let output = pll_output(input, &factors);
+            if output > target {
+                continue;
+            }
+            if best.map_or(true, |(_, best_output)| output > best_output) {
+                best = Some((factors, output));
+            }
+        }
+    }
+    best.map(|(factors, _)| factors)
+}
+
+/// Computes the SSCGR value for a modulation frequency and a depth in 0.01 % units
+fn spread_spectrum(
+    pll_input: u32,
+    plln: u32,
+    spread: Spread,
+    modulation: u32,
+    depth: u32,
+) -> Result<u32, Error> {
+    if modulation == 0 || modulation > MODULATION_MAX || depth == 0 ||
+        depth > MODULATION_DEPTH_MAX
+    {
+        return Err(Error::SpreadSpectrumOutOfRange);
+    }
+
+    // MODPER = round(fPLL_IN / (4 x fMod))
+    let modper = (pll_input + 2 * modulation) / (4 * modulation);
+    // INCSTEP = round((2^15 - 1) x md x PLLN / (100 x 5 x MODPER)), md in %
+    let numerator = INCSTEP_MAX as u64 * depth as u64 * plln as u64;
+    let denominator = 100 * 100 * 5 * modper as u64;
+    let incstep = ((numerator + denominator / 2) / denominator) as u32;
+
+    if modper == 0 || modper > MODPER_MAX || incstep == 0 || incstep > INCSTEP_MAX ||
+        modper * incstep > INCSTEP_MAX
+    {
+        return Err(Error::SpreadSpectrumOutOfRange);
+    }
+
+    Ok(
+        sscgr::SSCGEN | spread as u32 | incstep << sscgr::INCSTEP_SHIFT |
+            modper << sscgr::MODPER_SHIFT,
+    )
+}
+
+/// Searches the PLLI2S factors and I2S prescaler closest to `sample_rate`
+///
+/// `m` is the input divider already used by the main PLL, if any.
+fn search_plli2s(
+    input: u32,
+    m: Option<u32>,
+    sample_rate: u32,
+    mclk: bool,
+    chlen: spi::i2scfgr::Chlen,
+) -> Result<I2sClock, Error> {
+    if sample_rate < SAMPLE_RATE_MIN || sample_rate > SAMPLE_RATE_MAX {
+        return Err(Error::SampleRateUnreachable(sample_rate));
+    }
+
+    // I2SCLK cycles per sample period, divided by (2 * I2SDIV + ODD)
+    let per_sample: u64 = match (mclk, chlen) {
+        (true, _) => 256,
+        (false, spi::i2scfgr::Chlen::_16bitWide) => 32,
+        (false, spi::i2scfgr::Chlen::_32bitWide) => 64,
+    };
+    let (m_min, m_max) = match m {
+        Some(m) => (m, m),
+        None => (
+            cmp::max(PLLM_MIN, (input + PLL_INPUT_MAX - 1) / PLL_INPUT_MAX),
+            cmp::min(PLLM_MAX, input / PLL_INPUT_MIN),
+        ),
+    };
+    let target = sample_rate as u64 * 1000;
+
+    let mut best: Option<(I2sClock, u64)> = None;
+    for m in m_min..m_max + 1 {
+        for n in PLLI2SN_MIN..PLLI2SN_MAX + 1 {
+            let vco = input as u64 * n as u64 / m as u64;
+            if vco < PLL_VCO_MIN as u64 || vco > PLL_VCO_MAX as u64 {
+                continue;
+            }
+            for r in PLLI2SR_MIN..PLLI2SR_MAX + 1 {
+                let i2sclk = vco / r as u64;
+                if i2sclk > I2SCLK_MAX as u64 {
+                    continue;
+                }
+                // Nearest total prescaler 2 * I2SDIV + ODD
+                let step = per_sample * sample_rate as u64;
+                let total = (i2sclk + step / 2) / step;
+                let div = (total / 2) as u32;
+                if div < I2SDIV_MIN || div > I2SDIV_MAX {
+                    continue;
+                }
+
+                let achieved = i2sclk * 1000 / (per_sample * total);
+                let error = if achieved > target {
+                    achieved - target
+                } else {
+                    target - achieved
+                };
+                if best.map_or(true, |(_, best_error)| error < best_error) {
+                    let clock = I2sClock {
+                        m,
+                        n,
+                        r,
+                        div,
+                        odd: total % 2 == 1,
+                        mclk,
+                        i2sclk: i2sclk as u32,
+                        sample_rate_millihertz: achieved,
+                    };
+                    best = Some((clock, error));
+                }
+            }
+        }
+    }
+
+    best.map(|(clock, _)| clock).ok_or(
+        Error::SampleRateUnreachable(sample_rate),
+    )
+}
+
+fn pll_output(input: u32, pll: &PllFactors) -> u32 {
+    (input as u64 * pll.n as u64 / (pll.m as u64 * pll.p as u64)) as u32
+}
+
+/// Returns the smallest divider that brings `input` down to `target` or below
+fn select_divider(input: u32, target: u32, dividers: &[u32]) -> Option<u32> {
+    dividers.iter().cloned().find(|&div| input / div <= target)
+}
+
+/// Number of flash wait states needed at `hclk` for the supply voltage range
+fn flash_latency(hclk: u32, voltage: VoltageRange) -> u32 {
+    let step = match voltage {
+        VoltageRange::Vdd1V7To2V1 => 16_000_000,
+        VoltageRange::Vdd2V1To2V4 => 18_000_000,
+        VoltageRange::Vdd2V4To2V7 => 24_000_000,
+        VoltageRange::Vdd2V7To3V6 => 30_000_000,
+    };
+    (hclk - 1) / step
+}
+
+/// Sets the flash wait states and enables the caches and, when available, prefetch
+fn set_flash_latency(flash: &flash::FLASH, latency: u32, voltage: VoltageRange) {
+    let prften = match voltage {
+        VoltageRange::Vdd1V7To2V1 => flash::acr::Prften::Disable,
+        _ => flash::acr::Prften::Enable,
+    };
+    unsafe {
+        flash.acr.modify(|v| {
+            (v & !(flash::acr::LATENCY_MASK | flash::acr::Prften::Enable as u32)) | latency |
+                prften as u32 | flash::acr::Icen::Enable as u32 |
+                flash::acr::Dcen::Enable as u32
+        });
+    }
+    // The new latency must be in effect before the clock is raised
+    while flash.acr.read() & flash::acr::LATENCY_MASK != latency {}
+}
+
+/// Lowest regulator output voltage that supports `hclk`
+fn voltage_scale(hclk: u32) -> pwr::cr::Vos {
+    if hclk <= 60_000_000 {
+        pwr::cr::Vos::Scale3Mode
+    } else {
+        pwr::cr::Vos::Scale2Mode
+    }
+}
+
+/// Describes the clock tree running from HSI with the prescalers found in CFGR
+fn hsi_clocks(rcc: &RCC) -> Clocks {
+    let cfgr = rcc.cfgr.read();
+    let hpre = [2, 4, 8, 16, 64, 128, 256, 512]
+        .iter()
+        .cloned()
+        .find(|&div| hpre_bits(div) == cfgr & cfgr::HPRE_MASK)
+        .unwrap_or(1);
+    let ppre1 = [2, 4, 8, 16]
+        .iter()
+        .cloned()
+        .find(|&div| ppre1_bits(div) == cfgr & cfgr::PPRE1_MASK)
+        .unwrap_or(1);
+    let ppre2 = [2, 4, 8, 16]
+        .iter()
+        .cloned()
+        .find(|&div| ppre2_bits(div) == cfgr & cfgr::PPRE2_MASK)
+        .unwrap_or(1);
+    let timpre = if rcc.dckcfgr.read() & dckcfgr::TIMPRE_MASK == dckcfgr::Timpre::X4 as u32 {
+        dckcfgr::Timpre::X4
+    } else {
+        dckcfgr::Timpre::X2
+    };
+
+    let hclk = HSI_FREQUENCY / hpre;
+    let pclk1 = hclk / ppre1;
+    let pclk2 = hclk / ppre2;
+    Clocks {
+        sysclk: HSI_FREQUENCY,
+        hclk,
+        pclk1,
+        pclk2,
+        timclk1: timer_clock(timpre, hclk, pclk1, ppre1),
+        timclk2: timer_clock(timpre, hclk, pclk2, ppre2),
+        pll48clk: None,
+        i2s: None,
+        hse: None,
+    }
+}
+
+/// Timer clock of an APB bus for the given TIMPRE setting
+fn timer_clock(timpre: dckcfgr::Timpre, hclk: u32, pclk: u32, ppre: u32) -> u32 {
+    match timpre {
+        dckcfgr::Timpre::X2 if ppre == 1 => pclk,
+        dckcfgr::Timpre::X2 => pclk * 2,
+        dckcfgr::Timpre::X4 if ppre <= 4 => hclk,
+        dckcfgr::Timpre::X4 => pclk * 4,
+    }
+}
+
+fn pllp_bits(p: u32) -> u32 {
+    match p {
+        2 => pllcfgr::Pllp::_2 as u32,
+        4 => pllcfgr::Pllp::_4 as u32,
+        6 => pllcfgr::Pllp::_6 as u32,
+        _ => pllcfgr::Pllp::_8 as u32,
+    }
+}
+
+fn hpre_bits(div: u32) -> u32 {
+    match div {
+        1 => cfgr::Hpre::Div1 as u32,
+        2 => cfgr::Hpre::Div2 as u32,
+        4 => cfgr::Hpre::Div4 as u32,
+        8 => cfgr::Hpre::Div8 as u32,
+        16 => cfgr::Hpre::Div16 as u32,
+        64 => cfgr::Hpre::Div64 as u32,
+        128 => cfgr::Hpre::Div128 as u32,
+        256 => cfgr::Hpre::Div256 as u32,
+        _ => cfgr::Hpre::Div512 as u32,
+    }
+}
+
+fn ppre1_bits(div: u32) -> u32 {
+    match div {
+        1 => cfgr::Ppre1::Div1 as u32,
+        2 => cfgr::Ppre1::Div2 as u32,
+        4 => cfgr::Ppre1::Div4 as u32,
+        8 => cfgr::Ppre1::Div8 as u32,
+        _ => cfgr::Ppre1::Div16 as u32,
+    }
+}
+
+fn ppre2_bits(div: u32) -> u32 {
+    match div {
+        1 => cfgr::Ppre2::Div1 as u32,
+        2 => cfgr::Ppre2::Div2 as u32,
+        4 => cfgr::Ppre2::Div4 as u32,
+        8 => cfgr::Ppre2::Div8 as u32,
+        _ => cfgr::Ppre2::Div16 as u32,
+    }
+}
+
+/// Polls `ready` at most `READY_TIMEOUT` times
+fn wait_for<F>(ready: F, error: Error) -> Result<(), Error>
+where
+    F: Fn() -> bool,
+{
+    poll(ready, READY_TIMEOUT, error)
+}
+
+/// Polls `ready` at most `limit` times
+fn poll<F>(ready: F, limit: u32, error: Error) -> Result<(), Error>
+where
+    F: Fn() -> bool,
+{
+    for _ in 0..limit {
+        if ready() {
+            return Ok(());
+        }
+    }
+    Err(error)
+}
+
+fn enable_hsi(rcc: &RCC) -> Result<(), Error> {
+    unsafe { rcc.cr.modify(|v| v | cr::Hsion::On as u32) }
+    wait_for(
+        || rcc.cr.read() & cr::Hsirdy::Ready as u32 == cr::Hsirdy::Ready as u32,
+        Error::HsiTimeout,
+    )
+}
+
+/// Starts HSE in the given mode, which must not be feeding SYSCLK or the PLL
+///
+/// HSE is stopped again when it does not become ready.
+fn enable_hse(rcc: &RCC, mode: HseMode) -> Result<(), Error> {
+    let hsebyp = match mode {
+        HseMode::Crystal => cr::Hsebyp::NotBypassed,
+        HseMode::Bypass => cr::Hsebyp::BypassedWithExternalClock,
+    } as u32;
+
+    // HSEBYP can only be written while HSE is off
+    if rcc.cr.read() & cr::Hsebyp::BypassedWithExternalClock as u32 != hsebyp {
+        unsafe { rcc.cr.modify(|v| v & !(cr::Hseon::On as u32)) }
+        wait_for(
+            || rcc.cr.read() & cr::Hserdy::Ready as u32 == cr::Hserdy::NotReady as u32,
+            Error::HseTimeout,
+        )?;
+        unsafe {
+            rcc.cr.modify(|v| {
+                (v & !(cr::Hsebyp::BypassedWithExternalClock as u32)) | hsebyp
+            })
+        }
+    }
+
+    unsafe { rcc.cr.modify(|v| v | cr::Hseon::On as u32) }
+    let ready = wait_for(
+        || rcc.cr.read() & cr::Hserdy::Ready as u32 == cr::Hserdy::Ready as u32,
+        Error::HseTimeout,
+    );
+    if ready.is_err() {
+        unsafe { rcc.cr.modify(|v| v & !(cr::Hseon::On as u32)) }
+    }
+    ready
+}
+
+fn switch_sysclk(rcc: &RCC, sw: cfgr::Sw, sws: cfgr::Sws) -> Result<(), Error> {
+    unsafe { rcc.cfgr.modify(|v| (v & !cfgr::SW_MASK) | sw as u32) }
+    let sws = sws as u32;
+    wait_for(
+        || rcc.cfgr.read() & cfgr::SWS_MASK == sws,
+        Error::SwitchTimeout,
+    )
+}
+
 pub mod cr {
     /// PLLI2S clock ready flag
     pub enum Plli2srdy {
@@ -99,13 +1758,16 @@ pub mod pllcfgr {
     /// Main PLL division factor for USB OTG FS,
     /// SDIO and random number generator clocks
     pub const PLLQ_MASK: u32 = 0x0F00_0000;
+    pub const PLLQ_SHIFT: u32 = 24;
     /// Main PLL and audio PLL(PLLI2S) entry clock source
     pub const PLLSRC_MASK: u32 = 0b1 << 22;
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Pllsrc {
         HsiClock = 0b0 << 22,
         HseOscillatorClock = 0b1 << 22,
     }
     /// Main PLL division factor for main system clock
+    pub const PLLP_MASK: u32 = 0b11 << 16;
     pub enum Pllp {
         _2 = 0b00 << 16,
         _4 = 0b01 << 16,
@@ -121,6 +1783,7 @@ pub mod pllcfgr {
 
 pub mod cfgr {
     /// Microcontroller clock output 2
+    pub const MCO2_MASK: u32 = 0b11 << 30;
     pub enum Moc2 {
         SystemClock = 0b00 << 30,
         Plli2sClock = 0b01 << 30,
@@ -128,25 +1791,31 @@ pub mod cfgr {
         PllClock = 0b11 << 30,
     }
     /// MCO2 prescaler
+    pub const MCO2PRE_MASK: u32 = 0b111 << 27;
     pub enum Moc2pre {
+        Div1 = 0b000 << 27,
         Div2 = 0b100 << 27,
         Div3 = 0b101 << 27,
         Div4 = 0b110 << 27,
         Div5 = 0b111 << 27,
     }
     /// MCO1 prescaler
+    pub const MCO1PRE_MASK: u32 = 0b111 << 24;
     pub enum Moc1pre {
+        Div1 = 0b000 << 24,
         Div2 = 0b100 << 24,
         Div3 = 0b101 << 24,
         Div4 = 0b110 << 24,
         Div5 = 0b111 << 24,
     }
     /// I2S clock selection
+    pub const I2SSRC_MASK: u32 = 0b1 << 23;
     pub enum I2ssrc {
         Plli2sClock = 0b0 << 23,
         ExternalClock = 0b1 << 23, // External clock mapped on the I2S_CKIN pin used as I2S clock source
     }
     /// Microcontroller clock output 1
+    pub const MCO1_MASK: u32 = 0b11 << 21;
     pub enum Mco1 {
         HsiClock = 0b00 << 21,
         LseOscillator = 0b01 << 21,
@@ -155,6 +1824,7 @@ pub mod cfgr {
     }
     /// HSE division factor for RTC clock
     pub const RTCPRE_MASK: u32 = 0x001F_0000;
+    pub const RTCPRE_SHIFT: u32 = 16;
     /// APB high-speed prescaler (APB2)
     pub const PPRE2_MASK: u32 = 0b111 << 13;
     pub enum Ppre2 {
@@ -204,6 +1874,278 @@ pub mod cfgr {
     }
 }
 
+pub mod cir {
+    /// Clock security system interrupt clear
+    pub const CSSC: u32 = 0b1 << 23;
+    /// Clock security system interrupt flag
+    pub const CSSF: u32 = 0b1 << 7;
+}
+
+pub mod bdcr {
+    /// Backup domain software reset
+    pub const BDRST: u32 = 0b1 << 16;
+    /// RTC clock enable
+    pub const RTCEN: u32 = 0b1 << 15;
+    /// RTC clock source selection
+    pub const RTCSEL_MASK: u32 = 0b11 << 8;
+    pub enum Rtcsel {
+        NoClock = 0b00 << 8,
+        Lse = 0b01 << 8,
+        Lsi = 0b10 << 8,
+        Hse = 0b11 << 8, // HSE divided by RTCPRE
+    }
+    /// External low-speed oscillator mode (drive strength)
+    pub const LSEMOD_MASK: u32 = 0b1 << 3;
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Lsemod {
+        LowPower = 0b0 << 3,
+        HighDrive = 0b1 << 3,
+    }
+    /// External low-speed oscillator bypass
+    pub enum Lsebyp {
+        NotBypassed = 0b0 << 2,
+        Bypassed = 0b1 << 2,
+    }
+    /// External low-speed oscillator ready
+    pub enum Lserdy {
+        NotReady = 0b0 << 1,
+        Ready = 0b1 << 1,
+    }
+    /// External low-speed oscillator enable
+    pub enum Lseon {
+        Off = 0b0 << 0,
+        On = 0b1 << 0,
+    }
+}
+
+pub mod csr {
+    /// Low-power reset flag
+    pub const LPWRRSTF: u32 = 0b1 << 31;
+    /// Window watchdog reset flag
+    pub const WWDGRSTF: u32 = 0b1 << 30;
+    /// Independent watchdog reset flag
+    pub const IWDGRSTF: u32 = 0b1 << 29;
+    /// Software reset flag
+    pub const SFTRSTF: u32 = 0b1 << 28;
+    /// POR/PDR reset flag
+    pub const PORRSTF: u32 = 0b1 << 27;
+    /// PIN reset flag
+    pub const PINRSTF: u32 = 0b1 << 26;
+    /// BOR reset flag
+    pub const BORRSTF: u32 = 0b1 << 25;
+    /// Remove reset flag
+    pub const RMVF: u32 = 0b1 << 24;
+    /// Internal low-speed oscillator ready
+    pub enum Lsirdy {
+        NotReady = 0b0 << 1,
+        Ready = 0b1 << 1,
+    }
+    /// Internal low-speed oscillator enable
+    pub enum Lsion {
+        Off = 0b0 << 0,
+        On = 0b1 << 0,
+    }
+}
+
+pub mod sscgr {
+    /// Spread spectrum modulation enable
+    pub const SSCGEN: u32 = 0b1 << 31;
+    /// Spread select
+    pub const SPREADSEL: u32 = 0b1 << 30;
+    /// Incrementation step
+    pub const INCSTEP_MASK: u32 = 0x7FFF << 13;
+    pub const INCSTEP_SHIFT: u32 = 13;
+    /// Modulation period
+    pub const MODPER_MASK: u32 = 0x1FFF;
+    pub const MODPER_SHIFT: u32 = 0;
+}
+
+pub mod plli2scfgr {
+    /// PLLI2S division factor for I2S clocks
+    pub const PLLI2SR_MASK: u32 = 0b111 << 28;
+    pub const PLLI2SR_SHIFT: u32 = 28;
+    /// PLLI2S multiplication factor for VCO
+    pub const PLLI2SN_MASK: u32 = 0x1FF << 6;
+    pub const PLLI2SN_SHIFT: u32 = 6;
+}
+
+pub mod ahb1rstr {
+    /// DMA2 reset
+    pub enum Dma2rst {
+        NotReset = 0b0 << 22,
+        Reset = 0b1 << 22,
+    }
+    /// DMA1 reset
+    pub enum Dma1rst {
+        NotReset = 0b0 << 21,
+        Reset = 0b1 << 21,
+    }
+    /// CRC reset
+    pub enum Crcrst {
+        NotReset = 0b0 << 12,
+        Reset = 0b1 << 12,
+    }
+    /// IO port H reset
+    pub enum Gpiohrst {
+        NotReset = 0b0 << 7,
+        Reset = 0b1 << 7,
+    }
+    /// IO port E reset
+    pub enum Gpioerst {
+        NotReset = 0b0 << 4,
+        Reset = 0b1 << 4,
+    }
+    /// IO port D reset
+    pub enum Gpiodrst {
+        NotReset = 0b0 << 3,
+        Reset = 0b1 << 3,
+    }
+    /// IO port C reset
+    pub enum Gpiocrst {
+        NotReset = 0b0 << 2,
+        Reset = 0b1 << 2,
+    }
+    /// IO port B reset
+    pub enum Gpiobrst {
+        NotReset = 0b0 << 1,
+        Reset = 0b1 << 1,
+    }
+    /// IO port A reset
+    pub enum Gpioarst {
+        NotReset = 0b0 << 0,
+        Reset = 0b1 << 0,
+    }
+}
+
+pub mod ahb2rstr {
+    /// USB OTG FS reset
+    pub enum Otgfsrst {
+        NotReset = 0b0 << 7,
+        Reset = 0b1 << 7,
+    }
+}
+
+pub mod apb1rstr {
+    /// Power interface reset
+    pub enum Pwrrst {
+        NotReset = 0b0 << 28,
+        Reset = 0b1 << 28,
+    }
+    /// I2C3 reset
+    pub enum I2c3rst {
+        NotReset = 0b0 << 23,
+        Reset = 0b1 << 23,
+    }
+    /// I2C2 reset
+    pub enum I2c2rst {
+        NotReset = 0b0 << 22,
+        Reset = 0b1 << 22,
+    }
+    /// I2C1 reset
+    pub enum I2c1rst {
+        NotReset = 0b0 << 21,
+        Reset = 0b1 << 21,
+    }
+    /// USART2 reset
+    pub enum Usart2rst {
+        NotReset = 0b0 << 17,
+        Reset = 0b1 << 17,
+    }
+    /// SPI3 reset
+    pub enum Spi3rst {
+        NotReset = 0b0 << 15,
+        Reset = 0b1 << 15,
+    }
+    /// SPI2 reset
+    pub enum Spi2rst {
+        NotReset = 0b0 << 14,
+        Reset = 0b1 << 14,
+    }
+    /// Window watchdog reset
+    pub enum Wwdgrst {
+        NotReset = 0b0 << 11,
+        Reset = 0b1 << 11,
+    }
+    /// TIM5 reset
+    pub enum Tim5rst {
+        NotReset = 0b0 << 3,
+        Reset = 0b1 << 3,
+    }
+    /// TIM4 reset
+    pub enum Tim4rst {
+        NotReset = 0b0 << 2,
+        Reset = 0b1 << 2,
+    }
+    /// TIM3 reset
+    pub enum Tim3rst {
+        NotReset = 0b0 << 1,
+        Reset = 0b1 << 1,
+    }
+    /// TIM2 reset
+    pub enum Tim2rst {
+        NotReset = 0b0 << 0,
+        Reset = 0b1 << 0,
+    }
+}
+
+pub mod apb2rstr {
+    /// TIM11 reset
+    pub enum Tim11rst {
+        NotReset = 0b0 << 18,
+        Reset = 0b1 << 18,
+    }
+    /// TIM10 reset
+    pub enum Tim10rst {
+        NotReset = 0b0 << 17,
+        Reset = 0b1 << 17,
+    }
+    /// TIM9 reset
+    pub enum Tim9rst {
+        NotReset = 0b0 << 16,
+        Reset = 0b1 << 16,
+    }
+    /// System configuration controller reset
+    pub enum Syscfgrst {
+        NotReset = 0b0 << 14,
+        Reset = 0b1 << 14,
+    }
+    /// SPI4 reset
+    pub enum Spi4rst {
+        NotReset = 0b0 << 13,
+        Reset = 0b1 << 13,
+    }
+    /// SPI1 reset
+    pub enum Spi1rst {
+        NotReset = 0b0 << 12,
+        Reset = 0b1 << 12,
+    }
+    /// SDIO reset
+    pub enum Sdiorst {
+        NotReset = 0b0 << 11,
+        Reset = 0b1 << 11,
+    }
+    /// ADC1 reset
+    pub enum Adc1rst {
+        NotReset = 0b0 << 8,
+        Reset = 0b1 << 8,
+    }
+    /// USART6 reset
+    pub enum Usart6rst {
+        NotReset = 0b0 << 5,
+        Reset = 0b1 << 5,
+    }
+    /// USART1 reset
+    pub enum Usart1rst {
+        NotReset = 0b0 << 4,
+        Reset = 0b1 << 4,
+    }
+    /// TIM1 reset
+    pub enum Tim1rst {
+        NotReset = 0b0 << 0,
+        Reset = 0b1 << 0,
+    }
+}
+
 pub mod ahb1enr {
     /// DMA2 clock enable
     pub enum Dma2en {
@@ -252,6 +2194,14 @@ pub mod ahb1enr {
     }
 }
 
+pub mod ahb2enr {
+    /// USB OTG FS clock enable
+    pub enum Otgfsen {
+        Disable = 0b0 << 7,
+        Enable = 0b1 << 7,
+    }
+}
+
 pub mod apb1enr {
     /// Power interface clock enable
     pub enum Pwren {
@@ -315,9 +2265,255 @@ pub mod apb1enr {
     }
 }
 
+pub mod apb2enr {
+    /// TIM11 clock enable
+    pub enum Tim11en {
+        Disable = 0b0 << 18,
+        Enable = 0b1 << 18,
+    }
+    /// TIM10 clock enable
+    pub enum Tim10en {
+        Disable = 0b0 << 17,
+        Enable = 0b1 << 17,
+    }
+    /// TIM9 clock enable
+    pub enum Tim9en {
+        Disable = 0b0 << 16,
+        Enable = 0b1 << 16,
+    }
+    /// System configuration controller clock enable
+    pub enum Syscfgen {
+        Disable = 0b0 << 14,
+        Enable = 0b1 << 14,
+    }
+    /// SPI4 clock enable
+    pub enum Spi4en {
+        Disable = 0b0 << 13,
+        Enable = 0b1 << 13,
+    }
+    /// SPI1 clock enable
+    pub enum Spi1en {
+        Disable = 0b0 << 12,
+        Enable = 0b1 << 12,
+    }
+    /// SDIO clock enable
+    pub enum Sdioen {
+        Disable = 0b0 << 11,
+        Enable = 0b1 << 11,
+    }
+    /// ADC1 clock enable
+    pub enum Adc1en {
+        Disable = 0b0 << 8,
+        Enable = 0b1 << 8,
+    }
+    /// USART6 clock enable
+    pub enum Usart6en {
+        Disable = 0b0 << 5,
+        Enable = 0b1 << 5,
+    }
+    /// USART1 clock enable
+    pub enum Usart1en {
+        Disable = 0b0 << 4,
+        Enable = 0b1 << 4,
+    }
+    /// TIM1 clock enable
+    pub enum Tim1en {
+        Disable = 0b0 << 0,
+        Enable = 0b1 << 0,
+    }
+}
+
+pub mod ahb1lpenr {
+    /// DMA2 clock enable during Sleep mode
+    pub enum Dma2lpen {
+        Disable = 0b0 << 22,
+        Enable = 0b1 << 22,
+    }
+    /// DMA1 clock enable during Sleep mode
+    pub enum Dma1lpen {
+        Disable = 0b0 << 21,
+        Enable = 0b1 << 21,
+    }
+    /// SRAM1 interface clock enable during Sleep mode
+    pub enum Sram1lpen {
+        Disable = 0b0 << 16,
+        Enable = 0b1 << 16,
+    }
+    /// Flash interface clock enable during Sleep mode
+    pub enum Flitflpen {
+        Disable = 0b0 << 15,
+        Enable = 0b1 << 15,
+    }
+    /// CRC clock enable during Sleep mode
+    pub enum Crclpen {
+        Disable = 0b0 << 12,
+        Enable = 0b1 << 12,
+    }
+    /// IO port H clock enable during Sleep mode
+    pub enum Gpiohlpen {
+        Disable = 0b0 << 7,
+        Enable = 0b1 << 7,
+    }
+    /// IO port E clock enable during Sleep mode
+    pub enum Gpioelpen {
+        Disable = 0b0 << 4,
+        Enable = 0b1 << 4,
+    }
+    /// IO port D clock enable during Sleep mode
+    pub enum Gpiodlpen {
+        Disable = 0b0 << 3,
+        Enable = 0b1 << 3,
+    }
+    /// IO port C clock enable during Sleep mode
+    pub enum Gpioclpen {
+        Disable = 0b0 << 2,
+        Enable = 0b1 << 2,
+    }
+    /// IO port B clock enable during Sleep mode
+    pub enum Gpioblpen {
+        Disable = 0b0 << 1,
+        Enable = 0b1 << 1,
+    }
+    /// IO port A clock enable during Sleep mode
+    pub enum Gpioalpen {
+        Disable = 0b0 << 0,
+        Enable = 0b1 << 0,
+    }
+}
+
+pub mod ahb2lpenr {
+    /// USB OTG FS clock enable during Sleep mode
+    pub enum Otgfslpen {
+        Disable = 0b0 << 7,
+        Enable = 0b1 << 7,
+    }
+}
+
+pub mod apb1lpenr {
+    /// Power interface clock enable during Sleep mode
+    pub enum Pwrlpen {
+        Disable = 0b0 << 28,
+        Enable = 0b1 << 28,
+    }
+    /// I2C3 clock enable during Sleep mode
+    pub enum I2c3lpen {
+        Disable = 0b0 << 23,
+        Enable = 0b1 << 23,
+    }
+    /// I2C2 clock enable during Sleep mode
+    pub enum I2c2lpen {
+        Disable = 0b0 << 22,
+        Enable = 0b1 << 22,
+    }
+    /// I2C1 clock enable during Sleep mode
+    pub enum I2c1lpen {
+        Disable = 0b0 << 21,
+        Enable = 0b1 << 21,
+    }
+    /// USART2 clock enable during Sleep mode
+    pub enum Usart2lpen {
+        Disable = 0b0 << 17,
+        Enable = 0b1 << 17,
+    }
+    /// SPI3 clock enable during Sleep mode
+    pub enum Spi3lpen {
+        Disable = 0b0 << 15,
+        Enable = 0b1 << 15,
+    }
+    /// SPI2 clock enable during Sleep mode
+    pub enum Spi2lpen {
+        Disable = 0b0 << 14,
+        Enable = 0b1 << 14,
+    }
+    /// Window watchdog clock enable during Sleep mode
+    pub enum Wwdglpen {
+        Disable = 0b0 << 11,
+        Enable = 0b1 << 11,
+    }
+    /// TIM5 clock enable during Sleep mode
+    pub enum Tim5lpen {
+        Disable = 0b0 << 3,
+        Enable = 0b1 << 3,
+    }
+    /// TIM4 clock enable during Sleep mode
+    pub enum Tim4lpen {
+        Disable = 0b0 << 2,
+        Enable = 0b1 << 2,
+    }
+    /// TIM3 clock enable during Sleep mode
+    pub enum Tim3lpen {
+        Disable = 0b0 << 1,
+        Enable = 0b1 << 1,
+    }
+    /// TIM2 clock enable during Sleep mode
+    pub enum Tim2lpen {
+        Disable = 0b0 << 0,
+        Enable = 0b1 << 0,
+    }
+}
+
+pub mod apb2lpenr {
+    /// TIM11 clock enable during Sleep mode
+    pub enum Tim11lpen {
+        Disable = 0b0 << 18,
+        Enable = 0b1 << 18,
+    }
+    /// TIM10 clock enable during Sleep mode
+    pub enum Tim10lpen {
+        Disable = 0b0 << 17,
+        Enable = 0b1 << 17,
+    }
+    /// TIM9 clock enable during Sleep mode
+    pub enum Tim9lpen {
+        Disable = 0b0 << 16,
+        Enable = 0b1 << 16,
+    }
+    /// System configuration controller clock enable during Sleep mode
+    pub enum Syscfglpen {
+        Disable = 0b0 << 14,
+        Enable = 0b1 << 14,
+    }
+    /// SPI4 clock enable during Sleep mode
+    pub enum Spi4lpen {
+        Disable = 0b0 << 13,
+        Enable = 0b1 << 13,
+    }
+    /// SPI1 clock enable during Sleep mode
+    pub enum Spi1lpen {
+        Disable = 0b0 << 12,
+        Enable = 0b1 << 12,
+    }
+    /// SDIO clock enable during Sleep mode
+    pub enum Sdiolpen {
+        Disable = 0b0 << 11,
+        Enable = 0b1 << 11,
+    }
+    /// ADC1 clock enable during Sleep mode
+    pub enum Adc1lpen {
+        Disable = 0b0 << 8,
+        Enable = 0b1 << 8,
+    }
+    /// USART6 clock enable during Sleep mode
+    pub enum Usart6lpen {
+        Disable = 0b0 << 5,
+        Enable = 0b1 << 5,
+    }
+    /// USART1 clock enable during Sleep mode
+    pub enum Usart1lpen {
+        Disable = 0b0 << 4,
+        Enable = 0b1 << 4,
+    }
+    /// TIM1 clock enable during Sleep mode
+    pub enum Tim1lpen {
+        Disable = 0b0 << 0,
+        Enable = 0b1 << 0,
+    }
+}
+
 pub mod dckcfgr {
     /// Timers clocks prescalers selection
     pub const TIMPRE_MASK: u32 = 0b1 << 24;
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Timpre {
         X2 = 0b0 << 24,
         X4 = 0b1 << 24,
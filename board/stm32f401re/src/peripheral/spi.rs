@@ -1,4 +1,5 @@
 use volatile_register::{RO, RW};
+use peripheral::rcc::Clocks;
 
 pub const SPI2_BASE: u32 = 0x4000_3800;
 pub const SPI3_BASE: u32 = 0x4000_3C00;
@@ -18,6 +19,43 @@ pub struct RegisterMap {
     pub i2spr: RW<u32>,
 }
 
+peripheral!(
+    /// Serial peripheral interface 1
+    SPI1, SPI1_BASE
+);
+peripheral!(
+    /// Serial peripheral interface 2
+    SPI2, SPI2_BASE
+);
+peripheral!(
+    /// Serial peripheral interface 3
+    SPI3, SPI3_BASE
+);
+peripheral!(
+    /// Serial peripheral interface 4
+    SPI4, SPI4_BASE
+);
+
+macro_rules! kernel_clocks {
+    ($($SPIX:ident: $pclk:ident,)+) => {
+        $(
+            impl $SPIX {
+                /// Returns the APB clock that feeds the baud rate generator
+                pub fn clock(clocks: &Clocks) -> u32 {
+                    clocks.$pclk()
+                }
+            }
+        )+
+    }
+}
+
+kernel_clocks!(
+    SPI1: pclk2,
+    SPI2: pclk1,
+    SPI3: pclk1,
+    SPI4: pclk2,
+);
+
 pub mod cr1 {
     /// Bidirectional data mode enable
     pub enum Bidimode {
@@ -70,6 +108,7 @@ pub mod cr1 {
         Enable = 0b1 << 6,
     }
     /// Baud rate control (f_pclk/Br)
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Br {
         DIV2 = 0b000 << 3,
         DIV4 = 0b001 << 3,
@@ -80,6 +119,28 @@ pub mod cr1 {
         DIV128 = 0b110 << 3,
         DIV256 = 0b111 << 3,
     }
+    impl Br {
+        /// Returns the smallest divider that brings `pclk` down to `frequency` or below
+        pub fn from_frequency(pclk: u32, frequency: u32) -> Option<Br> {
+            [
+                Br::DIV2,
+                Br::DIV4,
+                Br::DIV8,
+                Br::DIV16,
+                Br::DIV32,
+                Br::DIV64,
+                Br::DIV128,
+                Br::DIV256,
+            ].iter()
+                .cloned()
+                .find(|&br| pclk / br.divider() <= frequency)
+        }
+
+        /// Division factor applied to PCLK
+        pub fn divider(self) -> u32 {
+            2 << (self as u32 >> 3)
+        }
+    }
     /// Master selection
     pub enum Mstr {
         Slave = 0b0 << 2,
@@ -226,6 +287,7 @@ pub mod i2scfgr {
         NotAllow = 0b11 << 1,
     }
     /// Channel length (number of bits per audio channel)
+    #[derive(Clone, Copy, Debug, PartialEq)]
     pub enum Chlen {
         _16bitWide = 0b0 << 0,
         _32bitWide = 0b1 << 0,
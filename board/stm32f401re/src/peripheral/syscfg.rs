@@ -1,4 +1,5 @@
 use volatile_register::RW;
+use peripheral::rcc::{self, Enable};
 
 pub const SYSCFG_BASE: u32 = 0x4001_3800;
 
@@ -13,6 +14,42 @@ pub struct RegisterMap {
     pub cmpcr: RW<u32>,
 }
 
+peripheral!(
+    /// System configuration controller
+    SYSCFG, SYSCFG_BASE
+);
+
+/// System configuration controller driver
+pub struct Syscfg {
+    syscfg: SYSCFG,
+}
+
+impl Syscfg {
+    /// Enables the SYSCFG clock and takes ownership of the SYSCFG peripheral
+    pub fn new(syscfg: SYSCFG, rcc: &rcc::RCC) -> Syscfg {
+        SYSCFG::enable(rcc);
+        Syscfg { syscfg }
+    }
+
+    /// Releases the SYSCFG peripheral
+    pub fn free(self) -> SYSCFG {
+        self.syscfg
+    }
+
+    /// Selects the GPIO port whose pin `line` drives EXTI line `line` (0..15)
+    pub fn set_exti_source(&mut self, line: u8, source: exticrx::Extix) {
+        assert!(line < 16);
+        let exticr = match line / 4 {
+            0 => &self.syscfg.exticr1,
+            1 => &self.syscfg.exticr2,
+            2 => &self.syscfg.exticr3,
+            _ => &self.syscfg.exticr4,
+        };
+        let shift = (line % 4) * 4;
+        unsafe { exticr.modify(|v| (v & !(0b1111 << shift)) | (source as u32) << shift) }
+    }
+}
+
 pub mod memrmp {
     /// Memory mapping selection
     pub enum MemMode {